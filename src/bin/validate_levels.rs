@@ -0,0 +1,65 @@
+//! Checks every map in the levels directory for problems without starting the game.
+//!
+//! Usage: `cargo run --bin validate_levels [levels directory]`. Exits with a non-zero status if
+//! any map has errors.
+
+extern crate toam;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use toam::level::validate::{self, Severity};
+
+fn main() {
+    let levels_dir = env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("resources/levels"));
+    // Tileset images are loaded relative to the resource directory, like in the game
+    let resource_dir = Path::new("resources");
+
+    let mut maps: Vec<PathBuf> = match fs::read_dir(&levels_dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "tmx"))
+            .collect(),
+        Err(e) => {
+            eprintln!("Could not read {}: {}", levels_dir.display(), e);
+            process::exit(2);
+        }
+    };
+    maps.sort();
+
+    let mut errors = 0;
+    let mut warnings = 0;
+
+    for map in &maps {
+        let problems = validate::validate_file(map, resource_dir);
+        if problems.is_empty() {
+            println!("{}: ok", map.display());
+            continue;
+        }
+
+        println!("{}:", map.display());
+        for problem in &problems {
+            println!("    {}", problem);
+            match problem.severity {
+                Severity::Error => errors += 1,
+                Severity::Warning => warnings += 1,
+            }
+        }
+    }
+
+    println!(
+        "Checked {} maps: {} errors, {} warnings",
+        maps.len(),
+        errors,
+        warnings
+    );
+
+    if errors > 0 {
+        process::exit(1);
+    }
+}
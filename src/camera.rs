@@ -0,0 +1,103 @@
+use cgmath::{Point2, Vector2};
+use ggez::graphics;
+
+/// How far the player can move from the centre of the view before the camera follows, in tiles
+const DEAD_ZONE: (f32, f32) = (1.5, 1.0);
+/// How far ahead of the player the camera looks in the direction they are facing, in tiles
+const LOOK_AHEAD: f32 = 2.0;
+/// How quickly the camera catches up with its target. Higher is snappier.
+const FOLLOW_SPEED: f32 = 5.0;
+
+/// The part of the level which is drawn on screen. This is a resource in the level's world.
+pub struct Camera {
+    /// The centre of the view, in tiles
+    pub pos: Point2<f32>,
+    /// How far ahead of the player the camera is currently looking, which eases towards
+    /// `LOOK_AHEAD` in the direction the player is facing
+    pub look_ahead: f32,
+    /// The size of the view, in tiles
+    pub view_size: Vector2<f32>,
+    /// The size of the map, in tiles. The view is kept inside of it.
+    pub map_size: Vector2<f32>,
+}
+
+impl Camera {
+    /// Creates a camera for a map of the given size, with a view of the given logical size
+    pub fn new(map_size: Vector2<f32>, logical_size: (f32, f32)) -> Self {
+        Camera {
+            pos: Point2::new(0.0, 0.0),
+            look_ahead: 0.0,
+            view_size: Vector2::new(logical_size.0 / 16.0, logical_size.1 / 16.0),
+            map_size,
+        }
+    }
+
+    /// Changes the size of the view to the given logical size, keeping the same centre
+    pub fn resize(&mut self, logical_size: (f32, f32)) {
+        self.view_size = Vector2::new(logical_size.0 / 16.0, logical_size.1 / 16.0);
+        self.clamp();
+    }
+
+    /// Moves the camera straight to the given point, without easing
+    pub fn look_at(&mut self, target: Point2<f32>) {
+        self.pos = target;
+        self.clamp();
+    }
+
+    /// Eases the camera towards the player.
+    ///
+    /// The camera doesn't move while the player stays within the dead zone around the centre of
+    /// the view, and leads the player by a few tiles in the direction they're facing.
+    pub fn follow(&mut self, player: Point2<f32>, facing: f32, delta_seconds: f32) {
+        let easing = 1.0 - (-FOLLOW_SPEED * delta_seconds).exp();
+        self.look_ahead += (facing * LOOK_AHEAD - self.look_ahead) * easing;
+
+        let target = Point2::new(player.x + self.look_ahead, player.y);
+        let offset = target - self.pos;
+
+        let mut desired = self.pos;
+        if offset.x.abs() > DEAD_ZONE.0 {
+            desired.x = target.x - DEAD_ZONE.0 * offset.x.signum();
+        }
+        if offset.y.abs() > DEAD_ZONE.1 {
+            desired.y = target.y - DEAD_ZONE.1 * offset.y.signum();
+        }
+
+        self.pos = self.pos + (desired - self.pos) * easing;
+        self.clamp();
+    }
+
+    /// Keeps the view inside of the map, or centres the map if it is smaller than the view
+    fn clamp(&mut self) {
+        let half_view = self.view_size / 2.0;
+
+        self.pos.x = if self.map_size.x <= self.view_size.x {
+            self.map_size.x / 2.0
+        } else {
+            self.pos.x.max(half_view.x).min(self.map_size.x - half_view.x)
+        };
+
+        self.pos.y = if self.map_size.y <= self.view_size.y {
+            self.map_size.y / 2.0
+        } else {
+            self.pos.y.max(half_view.y).min(self.map_size.y - half_view.y)
+        };
+    }
+
+    /// The position of the top left of the view, in tiles
+    pub fn origin(&self) -> Point2<f32> {
+        self.pos - self.view_size / 2.0
+    }
+
+    /// Converts a position in the level, in tiles, to a position on the screen, in logical pixels
+    pub fn to_screen(&self, pos: Point2<f32>) -> graphics::Point2 {
+        let origin = self.origin();
+        graphics::Point2::new((pos.x - origin.x) * 16.0, (pos.y - origin.y) * 16.0)
+    }
+
+    /// Converts a position on the screen, in logical pixels, to a position in the level, in tiles
+    pub fn to_world(&self, x: f32, y: f32) -> Point2<f32> {
+        let origin = self.origin();
+        Point2::new(x / 16.0 + origin.x, y / 16.0 + origin.y)
+    }
+}
@@ -6,13 +6,33 @@ use image::{DynamicImage, Pixel, Rgb, Rgba, RgbaImage};
 use itertools::Itertools;
 use std::path::Path;
 
-const IMAGE_SIZE: u16 = 128;
+/// The width and height of every painting, in pixels
+pub const IMAGE_SIZE: u16 = 128;
+/// The most colours a painting can have, since each one needs a button on the palette
+pub const MAX_COLOURS: usize = 16;
 const SPACING: f32 = 4.0;
 const BUTTON_SIZE: f32 = 20.0;
-const IMAGE_DRAW_LEFT: f32 = (::SCALED_SIZE.0) / 2.0 - IMAGE_SIZE as f32 - SPACING;
-const IMAGE_DRAW_RIGHT: f32 = (::SCALED_SIZE.0) / 2.0 + SPACING;
-const IMAGE_DRAW_TOP: f32 = (::SCALED_SIZE.1 - IMAGE_SIZE as f32) / 2.0;
-const IMAGE_DRAW_BOTTOM: f32 = (::SCALED_SIZE.1 + IMAGE_SIZE as f32) / 2.0;
+
+/// Where the two images are drawn, which everything else is placed around
+#[derive(Copy, Clone)]
+struct CanvasLayout {
+    left: f32,
+    right: f32,
+    top: f32,
+    bottom: f32,
+}
+
+impl CanvasLayout {
+    /// Centres the images on a screen of the given logical size
+    fn new(width: f32, height: f32) -> Self {
+        CanvasLayout {
+            left: width / 2.0 - IMAGE_SIZE as f32 - SPACING,
+            right: width / 2.0 + SPACING,
+            top: (height - IMAGE_SIZE as f32) / 2.0,
+            bottom: (height + IMAGE_SIZE as f32) / 2.0,
+        }
+    }
+}
 
 // TODO: Don't really want to clone this...
 #[derive(Clone)]
@@ -32,7 +52,7 @@ impl gui::ButtonType<CanvasState> for CanvasButton {
                 state.selected_color = color;
             }
             CanvasButton::Done => {
-                // TODO: Restioson
+                state.done = true;
             }
         }
     }
@@ -42,18 +62,23 @@ impl gui::ButtonType<CanvasState> for CanvasButton {
 pub struct CanvasState {
     selected_color: Rgba<u8>,
     brush_size: u8,
+    /// Whether the done button has been pressed
+    done: bool,
 }
 
 impl gui::GuiState for CanvasState {}
 
 /// An in game painting canvas for drawing to
 pub struct PaintingCanvas {
+    original: RgbaImage,
     original_gpu_image: graphics::Image,
     reproduction: RgbaImage,
     reproduction_gpu_image: Option<graphics::Image>,
     color_palette: Vec<Rgb<u8>>,
     changed: bool,
     component_holder: gui::GuiComponents<CanvasState, CanvasButton>,
+    layout: CanvasLayout,
+    size: (f32, f32),
     mouse_down: bool,
     last_draw_point: Option<(f32, f32)>,
     state: CanvasState,
@@ -65,14 +90,18 @@ impl PaintingCanvas {
 
         let color_palette: Vec<Rgb<u8>> = original.to_rgb().pixels().unique().cloned().collect();
         assert!(
-            color_palette.len() <= 16,
+            color_palette.len() <= MAX_COLOURS,
             "Paintings to reproduce cannot have more than 16 colours!"
         );
 
+        // Laid out for the smallest screen until the gui is resized
+        let size = (::MIN_LOGICAL_SIZE.0 as f32, ::MIN_LOGICAL_SIZE.1 as f32);
+        let layout = CanvasLayout::new(size.0, size.1);
+
         let mut buttons = vec![
             gui::Button::new(
                 CanvasButton::ModSize(1),
-                graphics::Point2::new(IMAGE_DRAW_LEFT, IMAGE_DRAW_BOTTOM + SPACING),
+                graphics::Point2::new(layout.left, layout.bottom + SPACING),
                 graphics::Point2::new(BUTTON_SIZE, BUTTON_SIZE),
                 Some(graphics::Image::new(ctx, "/plus_button.png").expect("Error loading image!")),
                 graphics::Color::new(0.9, 0.9, 0.9, 1.0),
@@ -81,8 +110,8 @@ impl PaintingCanvas {
             gui::Button::new(
                 CanvasButton::ModSize(-1),
                 graphics::Point2::new(
-                    IMAGE_DRAW_LEFT + BUTTON_SIZE + SPACING,
-                    IMAGE_DRAW_BOTTOM + SPACING,
+                    layout.left + BUTTON_SIZE + SPACING,
+                    layout.bottom + SPACING,
                 ),
                 graphics::Point2::new(BUTTON_SIZE, BUTTON_SIZE),
                 Some(graphics::Image::new(ctx, "/minus_button.png").expect("Error loading image!")),
@@ -92,8 +121,8 @@ impl PaintingCanvas {
             gui::Button::new(
                 CanvasButton::Done,
                 graphics::Point2::new(
-                    IMAGE_DRAW_RIGHT + IMAGE_SIZE as f32 - BUTTON_SIZE,
-                    IMAGE_DRAW_BOTTOM + SPACING,
+                    layout.right + IMAGE_SIZE as f32 - BUTTON_SIZE,
+                    layout.bottom + SPACING,
                 ),
                 graphics::Point2::new(BUTTON_SIZE, BUTTON_SIZE),
                 Some(graphics::Image::new(ctx, "/done_button.png").expect("Error loading image!")),
@@ -113,9 +142,9 @@ impl PaintingCanvas {
             buttons.push(gui::Button::new(
                 CanvasButton::ColorPalette(color.to_rgba()),
                 graphics::Point2::new(
-                    (::SCALED_SIZE.0 - BUTTON_SIZE) / 2.0
+                    (size.0 - BUTTON_SIZE) / 2.0
                         + (index as f32 - center_index) * (BUTTON_SIZE + SPACING),
-                    IMAGE_DRAW_TOP - BUTTON_SIZE - SPACING,
+                    layout.top - BUTTON_SIZE - SPACING,
                 ),
                 graphics::Point2::new(BUTTON_SIZE, BUTTON_SIZE),
                 None,
@@ -130,23 +159,28 @@ impl PaintingCanvas {
         }
 
         let selected_color = color_palette[0].to_rgba();
+        let original = original.to_rgba();
         PaintingCanvas {
             original_gpu_image: graphics::Image::from_rgba8(
                 ctx,
                 IMAGE_SIZE,
                 IMAGE_SIZE,
-                &original.to_rgba().into_raw(),
+                &original.clone().into_raw(),
             ).unwrap(),
+            original,
             reproduction: DynamicImage::new_rgba8(IMAGE_SIZE as u32, IMAGE_SIZE as u32).to_rgba(),
             reproduction_gpu_image: None,
             color_palette,
             changed: false,
             component_holder: gui::GuiComponents::new(buttons),
+            layout,
+            size,
             mouse_down: false,
             last_draw_point: None,
             state: CanvasState {
                 selected_color,
                 brush_size: 2,
+                done: false,
             },
         }
     }
@@ -183,7 +217,7 @@ impl PaintingCanvas {
     }
 
     pub fn paint_point(&mut self, (x, y): (f32, f32)) {
-        let (x, y) = ((x - IMAGE_DRAW_LEFT) as i32, (y - IMAGE_DRAW_TOP) as i32);
+        let (x, y) = ((x - self.layout.left) as i32, (y - self.layout.top) as i32);
         let radius = self.state.brush_size as i32;
         let radius_squared = radius * radius;
 
@@ -233,16 +267,43 @@ impl PaintingCanvas {
         )
     }
 
-    pub fn in_drawing_canvas(mouse_x: f32, mouse_y: f32) -> bool {
-        mouse_x >= IMAGE_DRAW_LEFT && mouse_x <= IMAGE_DRAW_RIGHT && mouse_y <= IMAGE_DRAW_BOTTOM
-            && mouse_y >= IMAGE_DRAW_TOP
+    /// How much of the original the reproduction matches, from 0 to 1. Transparent pixels in the
+    /// original aren't counted.
+    pub fn accuracy(&self) -> f32 {
+        let (matching, total) = self.original
+            .pixels()
+            .zip(self.reproduction.pixels())
+            .filter(|&(original, _)| original.data[3] > 0)
+            .fold((0, 0), |(matching, total), (original, reproduction)| {
+                (matching + (original == reproduction) as u32, total + 1)
+            });
+
+        if total == 0 {
+            1.0
+        } else {
+            matching as f32 / total as f32
+        }
+    }
+
+    pub fn in_drawing_canvas(&self, mouse_x: f32, mouse_y: f32) -> bool {
+        mouse_x >= self.layout.left && mouse_x <= self.layout.right
+            && mouse_y <= self.layout.bottom && mouse_y >= self.layout.top
     }
 }
 
 impl gui::Gui for PaintingCanvas {
+    fn resize(&mut self, width: f32, height: f32) {
+        // Everything is placed relative to the images, so it all moves along with them
+        let layout = CanvasLayout::new(width, height);
+        self.component_holder
+            .translate(layout.left - self.layout.left, layout.top - self.layout.top);
+        self.layout = layout;
+        self.size = (width, height);
+    }
+
     fn update(&mut self, mouse_x: f32, mouse_y: f32) -> GameResult<()> {
         if self.mouse_down {
-            if PaintingCanvas::in_drawing_canvas(mouse_x, mouse_y) {
+            if self.in_drawing_canvas(mouse_x, mouse_y) {
                 let current_point = (mouse_x, mouse_y);
                 match self.last_draw_point {
                     Some(point) => self.paint_line(point, current_point),
@@ -256,11 +317,11 @@ impl gui::Gui for PaintingCanvas {
     }
 
     fn draw(&mut self, ctx: &mut ggez::Context, _font: &graphics::Font, mouse_x: f32, mouse_y: f32) -> GameResult<()> {
-        gui::draw_rectangle(ctx, graphics::Point2::new(0.0, 0.0), graphics::Point2::new(::SCALED_SIZE.0, ::SCALED_SIZE.1), graphics::Color::new(0.1, 0.1, 0.1, 0.8))?;
+        gui::draw_rectangle(ctx, graphics::Point2::new(0.0, 0.0), graphics::Point2::new(self.size.0, self.size.1), graphics::Color::new(0.1, 0.1, 0.1, 0.8))?;
 
         let positions_of_canvases: [graphics::Point2; 2] = [
-            graphics::Point2::new(IMAGE_DRAW_LEFT, IMAGE_DRAW_TOP),
-            graphics::Point2::new(IMAGE_DRAW_RIGHT, IMAGE_DRAW_TOP),
+            graphics::Point2::new(self.layout.left, self.layout.top),
+            graphics::Point2::new(self.layout.right, self.layout.top),
         ];
 
         let (reproduction, original) = self.ggez_images(ctx);
@@ -271,10 +332,10 @@ impl gui::Gui for PaintingCanvas {
                 ctx,
                 graphics::DrawMode::Fill,
                 graphics::Rect::new(
-                    (pos.x - 1.0) * ::GLOBAL_SCALE,
-                    (pos.y - 1.0) * ::GLOBAL_SCALE,
-                    (image.width() as f32 + 2.0) * ::GLOBAL_SCALE,
-                    (image.height() as f32 + 2.0) * ::GLOBAL_SCALE,
+                    pos.x - 1.0,
+                    pos.y - 1.0,
+                    image.width() as f32 + 2.0,
+                    image.height() as f32 + 2.0,
                 ),
             )?;
 
@@ -283,9 +344,9 @@ impl gui::Gui for PaintingCanvas {
                 *image,
                 graphics::DrawParam {
                     src: graphics::Rect::one(),
-                    dest: graphics::Point2::new(pos.x * ::GLOBAL_SCALE, pos.y * ::GLOBAL_SCALE),
+                    dest: *pos,
                     rotation: 0.0,
-                    scale: graphics::Point2::new(::GLOBAL_SCALE, ::GLOBAL_SCALE),
+                    scale: graphics::Point2::new(1.0, 1.0),
                     offset: graphics::Point2::new(0.0, 0.0),
                     shear: graphics::Point2::new(0.0, 0.0),
                     color: None,
@@ -312,4 +373,16 @@ impl gui::Gui for PaintingCanvas {
         self.last_draw_point = None;
         self.mouse_down = false;
     }
+
+    fn poll_outcome(&mut self) -> Option<gui::GuiOutcome> {
+        if self.state.done {
+            self.state.done = false;
+            Some(gui::GuiOutcome::PaintingFinished {
+                accuracy: self.accuracy(),
+                replica: self.reproduction.clone(),
+            })
+        } else {
+            None
+        }
+    }
 }
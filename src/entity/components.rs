@@ -8,6 +8,7 @@ pub fn register_components(world: &mut World) {
     world.register::<Directional>();
     world.register::<CollisionState>();
     world.register::<GuardAi>();
+    world.register::<Distraction>();
 }
 
 /// A component for the player only
@@ -21,6 +22,40 @@ impl Component for Player {
 pub struct CollisionState {
     pub ground: bool,
     pub jump_cooldown: f32,
+    /// The width and height of the body
+    pub size: (f32, f32),
+    /// Whether the body was left standing on a one way platform last frame
+    pub on_platform: bool,
+    /// How much longer the body falls through one way platforms for
+    pub drop_through: f32,
+    /// Which side of the body a wall is touching, if any
+    pub wall: Option<Direction>,
+    /// Whether the body is sliding down the wall it's touching, which slows its fall
+    pub wall_sliding: bool,
+    /// Whether the body is climbing a ladder. Gravity doesn't pull on climbing bodies.
+    pub climbing: bool,
+    /// The corner of the ledge the body is hanging from, if it's hanging from one. Gravity doesn't
+    /// pull on hanging bodies either.
+    pub hanging: Option<::cgmath::Point2<f32>>,
+    /// How much longer until the body can grab onto a ledge again after letting go of one
+    pub grab_cooldown: f32,
+}
+
+impl CollisionState {
+    pub fn new(size: (f32, f32)) -> Self {
+        CollisionState {
+            ground: false,
+            jump_cooldown: 0.0,
+            size,
+            on_platform: false,
+            drop_through: 0.0,
+            wall: None,
+            wall_sliding: false,
+            climbing: false,
+            hanging: None,
+            grab_cooldown: 0.0,
+        }
+    }
 }
 
 impl Component for CollisionState {
@@ -31,11 +66,133 @@ pub struct GuardAi {
     pub state: GuardAiState,
     pub info: ::level::GuardInfo,
     pub turn_around_cooldown: f32,
+    /// The index of the waypoint in the patrol route the guard is walking towards
+    pub waypoint: usize,
+    /// Whether the guard is walking its route backwards (for routes that don't loop)
+    pub reversed: bool,
+    /// How much longer the guard stands still at the current waypoint
+    pub wait_timer: f32,
+    /// How suspicious the guard is that someone is around, from 0 to 1. It fills while the guard
+    /// can see the player and drains away while it can't.
+    pub suspicion: f32,
+    /// Where the guard last saw the player
+    pub last_seen: Option<::cgmath::Point2<f32>>,
+    /// Which way the player was moving when the guard last saw them, in tiles per second
+    pub last_seen_velocity: ::cgmath::Vector2<f32>,
+    /// Where the guard heard a noise it is going to look into
+    pub heard_noise: Option<::cgmath::Point2<f32>>,
+    /// Where the guard is heading to look for the player while searching
+    pub search_target: Option<::cgmath::Point2<f32>>,
+    /// How much longer the guard keeps searching for before going back to its patrol
+    pub search_timer: f32,
+    /// The edges of the navigation graph the guard is following, or `None` if there's no way to
+    /// where it's going
+    pub path: Option<Vec<::level::NavEdge>>,
+    /// Where the guard's path leads
+    pub path_target: Option<::cgmath::Point2<f32>>,
+    /// How much longer until the guard works out its path again
+    pub repath_timer: f32,
+    /// The index of the painting the guard has stopped to look at, if it is inspecting one
+    pub inspecting: Option<usize>,
+    /// How much longer the guard looks at the painting it is inspecting for
+    pub inspect_timer: f32,
+    /// The paintings this guard has already inspected, which it won't stop at again
+    pub inspected: Vec<usize>,
+    /// Whether the guard could see the player last frame
+    pub saw_player: bool,
+    /// The hiding spot the guard knows the player is in, because it saw them hide there or found
+    /// them while searching it
+    pub known_hiding_spot: Option<usize>,
+    /// The hiding spots the guard has already looked behind while searching
+    pub searched_hiding_spots: Vec<usize>,
+    /// How far below straight ahead the guard is looking, in radians
+    pub look_angle: f32,
+    /// How far through its sweep a camera is, in seconds
+    pub sweep_timer: f32,
+    /// How long until a chasing dog barks again
+    pub bark_timer: f32,
+    /// How long the guard has been chasing the player for, in seconds
+    pub chase_timer: f32,
+    /// The last alarm report the guard answered
+    pub alarm_report: u32,
+}
+
+impl GuardAi {
+    pub fn new(info: ::level::GuardInfo) -> Self {
+        let look_angle = info.pitch;
+        GuardAi {
+            state: GuardAiState::Patrolling,
+            info,
+            turn_around_cooldown: 0.0,
+            waypoint: 0,
+            reversed: false,
+            wait_timer: 0.0,
+            suspicion: 0.0,
+            last_seen: None,
+            last_seen_velocity: ::cgmath::Vector2::new(0.0, 0.0),
+            heard_noise: None,
+            search_target: None,
+            search_timer: 0.0,
+            path: None,
+            path_target: None,
+            repath_timer: 0.0,
+            inspecting: None,
+            inspect_timer: 0.0,
+            inspected: Vec::new(),
+            saw_player: false,
+            known_hiding_spot: None,
+            searched_hiding_spots: Vec::new(),
+            look_angle,
+            sweep_timer: 0.0,
+            bark_timer: 0.0,
+            chase_timer: 0.0,
+            alarm_report: 0,
+        }
+    }
+
+    /// The waypoint the guard is currently walking towards, if it has a patrol route
+    pub fn current_waypoint(&self) -> Option<&::level::Waypoint> {
+        self.info
+            .route
+            .as_ref()
+            .and_then(|route| route.waypoints.get(self.waypoint))
+    }
+
+    /// Moves on to the next waypoint, either wrapping around or walking back along the route
+    /// once the end is reached
+    pub fn advance_waypoint(&mut self) {
+        let (count, looping) = match self.info.route {
+            Some(ref route) => (route.waypoints.len(), route.looping),
+            None => return,
+        };
+
+        if count < 2 {
+            return;
+        }
+
+        if looping {
+            self.waypoint = (self.waypoint + 1) % count;
+        } else {
+            if self.reversed && self.waypoint == 0 {
+                self.reversed = false;
+            } else if !self.reversed && self.waypoint == count - 1 {
+                self.reversed = true;
+            }
+
+            if self.reversed {
+                self.waypoint -= 1;
+            } else {
+                self.waypoint += 1;
+            }
+        }
+    }
 }
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub enum GuardAiState {
     Searching,
+    /// Has caught a glimpse of something and is going to take a closer look
+    Suspicious,
     Chasing,
     Patrolling,
 }
@@ -64,7 +221,7 @@ impl Component for Directional {
 }
 
 /// The direction something is loooking in or moving in
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub enum Direction {
     Left,
     Right,
@@ -94,6 +251,8 @@ pub struct Sprite {
     pub rotation: f32,
     pub clip: Rect,
     pub scale: graphics::Point2,
+    /// The colour the image is tinted with, if any
+    pub color: Option<graphics::Color>,
 }
 
 impl Sprite {
@@ -103,6 +262,7 @@ impl Sprite {
             rotation: 0.0,
             clip: Rect::one(),
             scale: graphics::Point2::new(1.0, 1.0),
+            color: None,
         }
     }
 }
@@ -110,3 +270,24 @@ impl Sprite {
 impl Component for Sprite {
     type Storage = HashMapStorage<Self>;
 }
+
+/// Something the player has thrown, which makes a noise where it lands
+pub struct Distraction {
+    /// Whether it has hit something yet. It only makes a noise the first time it does.
+    pub landed: bool,
+    /// How much longer it lies around for after landing before it is cleaned up
+    pub linger_timer: f32,
+}
+
+impl Distraction {
+    pub fn new() -> Self {
+        Distraction {
+            landed: false,
+            linger_timer: 0.0,
+        }
+    }
+}
+
+impl Component for Distraction {
+    type Storage = HashMapStorage<Self>;
+}
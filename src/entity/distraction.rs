@@ -0,0 +1,99 @@
+use cgmath::{InnerSpace, Point2, Vector2};
+use collision::{dbvt::query_ray, Ray2};
+use ord_subset::OrdSubsetIterExt;
+use rhusics_ecs::physics2d::DynamicBoundingVolumeTree2;
+use std;
+use super::resources::{GlobalPlayerState, OneWayPlatforms};
+
+/// The width and height of a thrown distraction, in tiles
+pub const DISTRACTION_SIZE: f32 = 0.25;
+/// How fast the player throws distractions, in tiles per second
+const THROW_SPEED: f32 = 14.0;
+/// How far above the player's centre distractions are thrown from, in tiles
+const THROW_HEIGHT: f32 = 0.5;
+/// How far in front of the player distractions are thrown from, so they don't start inside them
+const THROW_CLEARANCE: f32 = 0.05;
+/// The time step the aim arc is traced with, in seconds
+const ARC_STEP: f32 = 1.0 / 60.0;
+/// How far ahead the aim arc is traced, in seconds
+const ARC_TIME: f32 = 1.5;
+
+/// Where a distraction thrown by the player leaves their hand, and how fast it is going. The
+/// throw goes the way the player is facing, `angle` radians above horizontal.
+pub fn launch(player: &GlobalPlayerState, angle: f32) -> (Point2<f32>, Vector2<f32>) {
+    let facing = player.facing.multiplier();
+    let half_width = (player.bounds.max.x - player.bounds.min.x) / 2.0;
+    let origin = Point2::new(
+        player.pos.x + facing * (half_width + DISTRACTION_SIZE / 2.0 + THROW_CLEARANCE),
+        player.pos.y - THROW_HEIGHT,
+    );
+    let velocity = Vector2::new(facing * angle.cos(), -angle.sin()) * THROW_SPEED;
+
+    (origin, velocity)
+}
+
+/// The path a distraction thrown from `origin` at `velocity` will take, up to where it first hits
+/// something or lands on a one way platform. This steps through the flight the same way the
+/// physics does, with gravity and air friction, so the arc matches the throw. `player` is the id
+/// of the player's body, which the arc passes through.
+pub fn trajectory(
+    tree: &DynamicBoundingVolumeTree2<f32>,
+    platforms: &OneWayPlatforms,
+    player: u32,
+    origin: Point2<f32>,
+    velocity: Vector2<f32>,
+) -> Vec<Point2<f32>> {
+    let mut points = vec![origin];
+    let mut position = origin;
+    let mut velocity = velocity;
+
+    let mut time = 0.0;
+    while time < ARC_TIME {
+        velocity.y += ::GRAVITY_ACCEL * ARC_STEP;
+        velocity *= 1.0 - ::AIR_FRICTION;
+        let step = velocity * ARC_STEP;
+        let length = step.magnitude();
+        if length <= std::f32::EPSILON {
+            break;
+        }
+
+        let direction = step / length;
+        let hit = query_ray(tree, Ray2::new(position, direction))
+            .into_iter()
+            .filter(|&(hit, _point)| hit.value.id() != player)
+            .map(|(_hit, point)| (point - position).dot(direction))
+            .filter(|&distance| distance >= 0.0 && distance <= length)
+            .ord_subset_min();
+
+        // Distractions fall onto one way platforms like any other body, so the arc stops on them
+        // on the way down
+        let half_size = DISTRACTION_SIZE / 2.0;
+        let next = position + step;
+        let landing = if velocity.y >= 0.0 {
+            platforms
+                .landed_on(next.x, half_size, position.y + half_size, next.y + half_size)
+                .map(|platform| {
+                    let drop = (platform.min.y - half_size - position.y).max(0.0);
+                    length * (drop / step.y).min(1.0)
+                })
+        } else {
+            None
+        };
+        let hit = hit.into_iter().chain(landing).ord_subset_min();
+
+        match hit {
+            Some(distance) => {
+                points.push(position + direction * distance);
+                break;
+            }
+            None => {
+                position += step;
+                points.push(position);
+            }
+        }
+
+        time += ARC_STEP;
+    }
+
+    points
+}
@@ -0,0 +1,538 @@
+use cgmath::{InnerSpace, Point2, Vector2};
+use collision::{Aabb2, Discrete};
+use level::{GuardKind, NavEdgeKind, NavGraph};
+use ord_subset::OrdSubsetIterExt;
+use rand;
+use std;
+use super::components::*;
+use super::resources::*;
+
+/// How close (horizontally) a guard must get to a waypoint to count as having reached it
+const WAYPOINT_REACH_DISTANCE: f32 = 0.5;
+/// How close (horizontally) a guard must get to where it jumps or drops down from before it does
+const TAKE_OFF_DISTANCE: f32 = 0.2;
+/// How far the target of a guard's path has to move before the path is worked out again, in tiles
+const REPATH_DISTANCE: f32 = 1.0;
+/// How often a guard waiting at a waypoint turns around to look behind itself
+const LOOK_AROUND_INTERVAL: f32 = 1.0;
+/// How suspicious a patrolling guard has to get before it goes to take a closer look
+const SUSPICIOUS_THRESHOLD: f32 = 0.4;
+/// How long a guard stands looking at a forgery before deciding whether it's fake
+const INSPECT_TIME: f32 = 1.5;
+/// How far away a searching guard will go to look behind a hiding spot, in tiles
+const HIDING_SPOT_SEARCH_RANGE: f32 = 6.0;
+/// How long a guard searches for the player after losing them before going back to its patrol
+const SEARCH_TIME: f32 = 8.0;
+/// How much more suspicious a guard gets from hearing a noise right next to it
+const NOISE_SUSPICION: f32 = 0.3;
+/// The most suspicious a guard can get from noises alone, without seeing anyone
+const MAX_NOISE_SUSPICION: f32 = 0.9;
+/// How often a chasing dog barks, in seconds
+const BARK_INTERVAL: f32 = 1.2;
+/// How long a guard chases the player before it calls in the alarm, in seconds
+const CALL_IN_TIME: f32 = 2.0;
+/// How far ahead of where the player was last seen a searching guard looks, in seconds of the
+/// player's last known velocity
+const SEARCH_PREDICTION_TIME: f32 = 0.75;
+
+/// The parts of a guard which its state can change, along with what it can currently perceive
+pub struct Guard<'a> {
+    pub ai: &'a mut GuardAi,
+    pub directional: &'a mut Directional,
+    pub collision_state: &'a mut CollisionState,
+    pub position: Point2<f32>,
+    pub bound: Aabb2<f32>,
+    /// Whether the guard can see the player this frame
+    pub found_player: bool,
+    /// The horizontal walking force to apply this frame
+    pub walk_force: f32,
+    /// Whether the guard jumped this frame
+    pub jumped: bool,
+    /// Whether the guard barked this frame, which other guards can hear
+    pub barked: bool,
+}
+
+impl<'a> Guard<'a> {
+    /// Faces towards the given horizontal position
+    fn face_towards(&mut self, x: f32) {
+        self.directional.direction = if x > self.position.x {
+            Direction::Right
+        } else {
+            Direction::Left
+        };
+    }
+
+    /// Walks in the direction the guard is facing, with less force in the air
+    fn walk(&mut self, speed: f32, air_speed: f32) {
+        let speed = if self.collision_state.ground {
+            speed
+        } else {
+            speed.min(air_speed)
+        };
+        self.walk_force = self.directional.multiplier() * speed;
+    }
+
+    /// Jumps if the guard is on the ground and able to
+    fn jump(&mut self) {
+        if self.collision_state.ground && self.collision_state.jump_cooldown <= std::f32::EPSILON {
+            self.jumped = true;
+            self.collision_state.jump_cooldown = 0.25;
+        }
+    }
+
+    /// Turns around every so often to look behind itself
+    fn look_around(&mut self) {
+        if self.ai.turn_around_cooldown <= std::f32::EPSILON {
+            self.directional.direction = self.directional.direction.invert();
+            self.ai.turn_around_cooldown = LOOK_AROUND_INTERVAL;
+        }
+    }
+
+    /// Heads for `target` along the navigation graph, returning whether the guard has got there.
+    ///
+    /// The path is worked out again every so often, and whenever the target moves. If there is no
+    /// way there on the graph, the guard walks straight at it and uses any jump boxes on the way.
+    fn navigate_to(
+        &mut self,
+        target: Point2<f32>,
+        speed: f32,
+        air_speed: f32,
+        surroundings: &Surroundings,
+    ) -> bool {
+        let nav = surroundings.nav;
+        let feet = Point2::new(self.position.x, self.position.y + ::GUARD_SIZE.1 / 2.0);
+
+        self.ai.repath_timer -= surroundings.delta_seconds;
+        let moved = self.ai
+            .path_target
+            .map_or(true, |old| (old - target).magnitude() > REPATH_DISTANCE);
+        if moved || self.ai.repath_timer <= 0.0 {
+            self.ai.path = nav.find_path(feet, target);
+            self.ai.path_target = Some(target);
+            self.ai.repath_timer = self.ai.info.repath_interval;
+        }
+
+        // An edge has been followed once the guard is standing on the surface it leads to
+        if let Some(ref mut path) = self.ai.path {
+            let arrived = match path.first() {
+                Some(edge) => self.collision_state.ground && nav.standing_on(feet, edge.to),
+                None => false,
+            };
+            if arrived {
+                path.remove(0);
+            }
+        }
+
+        let next_edge = self.ai.path.as_ref().and_then(|path| path.first().cloned());
+        match next_edge {
+            Some(edge) => {
+                // Walk to the take off point, then jump or walk off the edge towards the landing
+                let direction = (edge.to_x - edge.from_x).signum();
+                let taking_off = (self.position.x - edge.from_x) * direction >= -TAKE_OFF_DISTANCE;
+
+                if self.collision_state.ground && !taking_off {
+                    self.face_towards(edge.from_x);
+                } else {
+                    self.face_towards(edge.to_x);
+                    if edge.kind == NavEdgeKind::Jump {
+                        self.jump();
+                    }
+                }
+                self.walk(speed, air_speed);
+
+                false
+            }
+            None => {
+                if (target.x - self.position.x).abs() < WAYPOINT_REACH_DISTANCE {
+                    return true;
+                }
+
+                self.face_towards(target.x);
+                self.walk(speed, air_speed);
+
+                if self.ai.path.is_none() && target.y < self.position.y {
+                    let direction = self.directional.direction;
+                    self.use_jump_boxes(direction, surroundings.jump_boxes);
+                }
+
+                false
+            }
+        }
+    }
+
+    /// Jumps at any jump box which leads in the given direction
+    fn use_jump_boxes(&mut self, direction: Direction, jump_boxes: &GuardJumpBoxes) {
+        let jump = jump_boxes
+            .0
+            .iter()
+            .any(|b| b.get_direction() == direction && self.bound.intersects(b.get_aabb2()));
+        if jump {
+            self.jump();
+        }
+    }
+}
+
+/// The rest of the level, as far as the guards are concerned
+pub struct Surroundings<'a> {
+    pub player: &'a mut GlobalPlayerState,
+    pub jump_boxes: &'a GuardJumpBoxes,
+    pub turn_around_boxes: &'a GuardTurnAroundBoxes,
+    pub paintings: &'a Paintings,
+    pub hiding_spots: &'a HidingSpots,
+    pub nav: &'a NavGraph,
+    pub alarm: &'a mut Alarm,
+    pub delta_seconds: f32,
+}
+
+/// Runs the guard's current state for a frame, returning the state it should be in next
+pub fn update(guard: &mut Guard, surroundings: &mut Surroundings) -> GuardAiState {
+    match guard.ai.state {
+        GuardAiState::Patrolling => patrol(guard, surroundings),
+        GuardAiState::Suspicious => investigate(guard, surroundings),
+        GuardAiState::Chasing => chase(guard, surroundings),
+        GuardAiState::Searching => search(guard, surroundings),
+    }
+}
+
+/// Sets the guard up for a state it has just switched to
+pub fn enter(guard: &mut Guard, state: GuardAiState) {
+    let ai = &mut *guard.ai;
+    ai.inspecting = None;
+
+    match state {
+        GuardAiState::Patrolling => {
+            ai.last_seen = None;
+            ai.heard_noise = None;
+        }
+        GuardAiState::Suspicious => (),
+        GuardAiState::Chasing => {
+            ai.searched_hiding_spots.clear();
+            ai.chase_timer = 0.0;
+        }
+        GuardAiState::Searching => {
+            // Head for where the player was going when they were last seen
+            ai.search_target = ai.last_seen
+                .map(|pos| pos + ai.last_seen_velocity * SEARCH_PREDICTION_TIME);
+            ai.search_timer = SEARCH_TIME;
+            ai.searched_hiding_spots.clear();
+        }
+    }
+}
+
+/// Reacts to a noise from `source`, which sounded `volume` loud from 0 to 1. A calm guard turns
+/// towards it and goes to take a look, and a searching guard searches there instead.
+pub fn hear(guard: &mut Guard, source: Point2<f32>, volume: f32) {
+    match guard.ai.state {
+        GuardAiState::Patrolling | GuardAiState::Suspicious => {
+            let ai = &mut *guard.ai;
+            let suspicion = ai.suspicion.max(SUSPICIOUS_THRESHOLD) + volume * NOISE_SUSPICION;
+            ai.suspicion = suspicion.min(MAX_NOISE_SUSPICION).max(ai.suspicion);
+            ai.heard_noise = Some(source);
+        }
+        GuardAiState::Searching => {
+            guard.ai.search_target = Some(source);
+            guard.ai.search_timer = SEARCH_TIME;
+        }
+        GuardAiState::Chasing => return,
+    }
+
+    if !guard.found_player {
+        guard.face_towards(source.x);
+    }
+}
+
+/// Sends the guard to search around `position`, where the alarm or another guard reported the
+/// player. A guard which is already searching searches there instead, and a chasing guard carries
+/// on chasing.
+pub fn converge(guard: &mut Guard, position: Option<Point2<f32>>) {
+    match guard.ai.state {
+        GuardAiState::Patrolling | GuardAiState::Suspicious => {
+            if position.is_some() {
+                guard.ai.last_seen = position;
+                guard.ai.last_seen_velocity = Vector2::new(0.0, 0.0);
+            }
+            enter(guard, GuardAiState::Searching);
+            guard.ai.state = GuardAiState::Searching;
+        }
+        GuardAiState::Searching => {
+            if position.is_some() {
+                guard.ai.search_target = position;
+            }
+            guard.ai.search_timer = SEARCH_TIME;
+        }
+        GuardAiState::Chasing => (),
+    }
+}
+
+/// Walks the patrol route, stopping to inspect any forgeries along the way
+fn patrol(guard: &mut Guard, surroundings: &mut Surroundings) -> GuardAiState {
+    if guard.ai.suspicion >= 1.0 {
+        return GuardAiState::Chasing;
+    } else if guard.ai.suspicion >= SUSPICIOUS_THRESHOLD {
+        return GuardAiState::Suspicious;
+    }
+
+    // Stop to look at any forgery the guard walks past which it hasn't checked yet, if it would
+    // ever be able to tell
+    if guard.ai.inspecting.is_none() && guard.ai.info.perceptiveness > 0.0 {
+        let bound = guard.bound;
+        let ai = &mut *guard.ai;
+        let forgery = surroundings
+            .paintings
+            .0
+            .iter()
+            .enumerate()
+            .find(|&(index, painting)| {
+                painting.status == PaintingStatus::Swapped && !ai.inspected.contains(&index)
+                    && painting.info.bounds.intersects(&bound)
+            });
+
+        if let Some((index, _painting)) = forgery {
+            ai.inspected.push(index);
+            ai.inspecting = Some(index);
+            ai.inspect_timer = INSPECT_TIME;
+        }
+    }
+
+    if let Some(index) = guard.ai.inspecting {
+        inspect(guard, surroundings, index);
+        return GuardAiState::Patrolling;
+    }
+
+    let speed = guard.ai.info.patrol_speed;
+    match guard.ai.current_waypoint().cloned() {
+        Some(waypoint) => {
+            if guard.ai.wait_timer > 0.0 {
+                guard.ai.wait_timer -= surroundings.delta_seconds;
+
+                if waypoint.look_around {
+                    guard.look_around();
+                }
+
+                if guard.ai.wait_timer <= 0.0 {
+                    guard.ai.advance_waypoint();
+                }
+            } else if guard.navigate_to(
+                waypoint.pos,
+                speed,
+                ::GUARD_AIR_PATROL_SPEED,
+                surroundings,
+            ) {
+                if waypoint.wait > 0.0 {
+                    guard.ai.wait_timer = waypoint.wait;
+                } else {
+                    guard.ai.advance_waypoint();
+                }
+            }
+        }
+        None => {
+            guard.walk(speed, ::GUARD_AIR_PATROL_SPEED);
+
+            let turn_around = surroundings
+                .turn_around_boxes
+                .0
+                .iter()
+                .any(|aabb| guard.bound.intersects(aabb));
+            if turn_around && guard.ai.turn_around_cooldown <= std::f32::EPSILON {
+                guard.directional.direction = guard.directional.direction.invert();
+                guard.ai.turn_around_cooldown = 0.75;
+            }
+        }
+    }
+
+    GuardAiState::Patrolling
+}
+
+/// Stands looking at a swapped painting, and locks the level down once done if it spots the
+/// forgery
+fn inspect(guard: &mut Guard, surroundings: &mut Surroundings, index: usize) {
+    let painting = &surroundings.paintings.0[index];
+    let centre = painting.info.bounds.min
+        + (painting.info.bounds.max - painting.info.bounds.min) / 2.0;
+    guard.face_towards(centre.x);
+
+    guard.ai.inspect_timer -= surroundings.delta_seconds;
+    if guard.ai.inspect_timer <= 0.0 {
+        guard.ai.inspecting = None;
+
+        let inaccuracy = 1.0 - painting.accuracy.unwrap_or(0.0);
+        let chance = (guard.ai.info.perceptiveness * inaccuracy).min(1.0);
+        if rand::random::<f32>() < chance {
+            surroundings.alarm.raise(AlarmLevel::Lockdown, Some(centre));
+        }
+    }
+}
+
+/// Watches the player while they're in sight, and otherwise goes to look where they were last
+/// seen or heard, until the guard is either sure or has forgotten about it
+fn investigate(guard: &mut Guard, surroundings: &mut Surroundings) -> GuardAiState {
+    if guard.ai.suspicion >= 1.0 {
+        return GuardAiState::Chasing;
+    } else if guard.ai.suspicion <= std::f32::EPSILON {
+        return GuardAiState::Patrolling;
+    }
+
+    if guard.found_player {
+        guard.face_towards(surroundings.player.pos.x);
+        return GuardAiState::Suspicious;
+    }
+
+    let speed = guard.ai.info.patrol_speed;
+    let arrived = match guard.ai.heard_noise.or(guard.ai.last_seen) {
+        Some(target) => guard.navigate_to(target, speed, ::GUARD_AIR_PATROL_SPEED, surroundings),
+        None => true,
+    };
+    if arrived {
+        guard.look_around();
+    }
+
+    GuardAiState::Suspicious
+}
+
+/// Runs after the player, catching them if it gets close enough
+fn chase(guard: &mut Guard, surroundings: &mut Surroundings) -> GuardAiState {
+    if !guard.found_player {
+        return GuardAiState::Searching;
+    }
+
+    guard.ai.suspicion = 1.0;
+
+    // Guards call the alarm in if they can't catch the player quickly, and keep reporting where
+    // they are for as long as they're in sight. Dogs just bark.
+    let player = surroundings.player.pos;
+    if guard.ai.info.kind != GuardKind::Dog {
+        let calling_in = guard.ai.chase_timer < CALL_IN_TIME;
+        guard.ai.chase_timer += surroundings.delta_seconds;
+        if calling_in && guard.ai.chase_timer >= CALL_IN_TIME {
+            surroundings.alarm.escalate(Some(player));
+        } else if surroundings.alarm.is_raised() {
+            surroundings.alarm.sighted(Some(player));
+        }
+    }
+
+    if guard.bound.intersects(&surroundings.player.bounds) {
+        surroundings.player.captured = true;
+    }
+
+    let speed = guard.ai.info.chase_speed;
+    if guard.navigate_to(player, speed, ::GUARD_AIR_CHASE_SPEED, surroundings) {
+        guard.face_towards(player.x);
+    }
+
+    // Dogs bark to bring the other guards over
+    if guard.ai.info.kind == GuardKind::Dog {
+        guard.ai.bark_timer -= surroundings.delta_seconds;
+        if guard.ai.bark_timer <= 0.0 {
+            guard.ai.bark_timer = BARK_INTERVAL;
+            guard.barked = true;
+        }
+    }
+
+    GuardAiState::Chasing
+}
+
+/// Sweeps a security camera's gaze back and forth, holding it still while the player is in sight,
+/// and raises the alarm once the camera is sure it has seen them, reporting where they are for as
+/// long as they stay in sight. Cameras don't move, so this takes the place of `update` for them.
+pub fn watch(
+    ai: &mut GuardAi,
+    found_player: bool,
+    alarm: &mut Alarm,
+    delta_seconds: f32,
+) -> GuardAiState {
+    if !found_player && ai.info.sweep_time > 0.0 {
+        ai.sweep_timer = (ai.sweep_timer + delta_seconds) % ai.info.sweep_time;
+        let phase = ai.sweep_timer / ai.info.sweep_time * 2.0 * std::f32::consts::PI;
+        ai.look_angle = ai.info.pitch + ai.info.sweep_angle / 2.0 * phase.sin();
+    }
+
+    if ai.suspicion >= 1.0 {
+        if ai.state != GuardAiState::Chasing {
+            alarm.escalate(ai.last_seen);
+        } else if found_player {
+            alarm.sighted(ai.last_seen);
+        }
+        GuardAiState::Chasing
+    } else if ai.suspicion >= SUSPICIOUS_THRESHOLD {
+        GuardAiState::Suspicious
+    } else {
+        GuardAiState::Patrolling
+    }
+}
+
+/// Heads to where the player was last seen going, then looks around and behind nearby hiding
+/// spots until it finds them again or gives up and goes back to its patrol
+fn search(guard: &mut Guard, surroundings: &mut Surroundings) -> GuardAiState {
+    if guard.found_player {
+        return GuardAiState::Chasing;
+    }
+
+    guard.ai.search_timer -= surroundings.delta_seconds;
+    if guard.ai.search_timer <= 0.0 {
+        if !surroundings.alarm.is_raised() {
+            return GuardAiState::Patrolling;
+        }
+
+        // Guards keep looking for as long as the alarm is raised
+        guard.ai.search_timer = SEARCH_TIME;
+        guard.ai.searched_hiding_spots.clear();
+    }
+
+    if let Some(target) = guard.ai.search_target {
+        let speed = guard.ai.info.chase_speed;
+        if !guard.navigate_to(target, speed, ::GUARD_AIR_CHASE_SPEED, surroundings) {
+            return GuardAiState::Searching;
+        }
+
+        guard.ai.search_target = None;
+    }
+
+    search_hiding_spots(guard, surroundings);
+
+    if guard.found_player {
+        GuardAiState::Chasing
+    } else {
+        GuardAiState::Searching
+    }
+}
+
+/// Looks behind the closest hiding spot the guard hasn't checked yet, or looks both ways if
+/// there are none left nearby
+fn search_hiding_spots(guard: &mut Guard, surroundings: &mut Surroundings) {
+    let position = guard.position;
+    let distance = |spot: &Aabb2<f32>| {
+        let centre = spot.min + (spot.max - spot.min) / 2.0;
+        (centre - position).magnitude()
+    };
+
+    let closest = {
+        let searched = &guard.ai.searched_hiding_spots;
+        surroundings
+            .hiding_spots
+            .0
+            .iter()
+            .enumerate()
+            .filter(|&(index, spot)| {
+                !searched.contains(&index) && distance(spot) <= HIDING_SPOT_SEARCH_RANGE
+            })
+            .ord_subset_min_by_key(|&(_index, spot)| distance(spot))
+    };
+
+    match closest {
+        Some((index, spot)) => {
+            if spot.intersects(&guard.bound) {
+                guard.ai.searched_hiding_spots.push(index);
+                if surroundings.player.hiding == Some(index) {
+                    guard.ai.known_hiding_spot = Some(index);
+                    guard.ai.suspicion = 1.0;
+                    guard.found_player = true;
+                }
+            } else {
+                let speed = guard.ai.info.patrol_speed;
+                let centre = spot.min + (spot.max - spot.min) / 2.0;
+                guard.navigate_to(centre, speed, ::GUARD_AIR_PATROL_SPEED, surroundings);
+            }
+        }
+        None => guard.look_around(),
+    }
+}
@@ -1,3 +1,8 @@
 pub mod components;
+pub mod distraction;
+mod guard_ai;
+pub mod noise;
+pub mod perception;
 pub mod resources;
 pub mod systems;
+pub mod vision;
@@ -0,0 +1,148 @@
+use cgmath::{InnerSpace, Point2, Vector2};
+use collision::{dbvt::query_ray, Aabb2, Ray2};
+use rhusics_ecs::physics2d::DynamicBoundingVolumeTree2;
+use std;
+use std::cmp::Ordering;
+
+/// How much of a noise's range is left after it passes through each wall or closed door
+const WALL_DAMPING: f32 = 0.5;
+/// How far apart two colliders along a noise's path can be and still count as one wall
+const WALL_GAP_TOLERANCE: f32 = 0.01;
+
+/// A sound made somewhere in the level, which guards nearby might hear
+#[derive(Debug, Copy, Clone)]
+pub struct NoiseEvent {
+    /// Where the sound came from
+    pub position: Point2<f32>,
+    /// How far away the sound can be heard in the open, in tiles
+    pub loudness: f32,
+}
+
+impl NoiseEvent {
+    /// How loud the noise sounds from `listener`, from 0 to 1, or `None` if it can't be heard.
+    ///
+    /// Sound fades with distance, and each wall or closed door in between cuts how far it
+    /// carries. Colliders which touch, like the tiles making up a thick wall, count as one wall.
+    /// `bodies` are the ids of the moving bodies, like guards and the player, which don't muffle
+    /// anything. `hearing` scales how far away the listener can hear from.
+    pub fn volume_at(
+        &self,
+        tree: &DynamicBoundingVolumeTree2<f32>,
+        listener: Point2<f32>,
+        bodies: &[u32],
+        hearing: f32,
+    ) -> Option<f32> {
+        let loudness = self.loudness * hearing;
+        let offset = self.position - listener;
+        let distance = offset.magnitude();
+        if distance > loudness || loudness <= 0.0 {
+            return None;
+        } else if distance <= std::f32::EPSILON {
+            return Some(1.0);
+        }
+
+        let direction = offset / distance;
+        let hits = query_ray(tree, Ray2::new(listener, direction))
+            .into_iter()
+            .filter(|&(hit, _point)| !bodies.contains(&hit.value.id()))
+            .map(|(hit, _point)| hit.bound)
+            .collect::<Vec<_>>();
+        let walls = walls_crossed(listener, direction, distance, &hits);
+
+        let range = loudness * WALL_DAMPING.powi(walls as i32);
+        if distance <= range {
+            Some(1.0 - distance / range)
+        } else {
+            None
+        }
+    }
+}
+
+/// How many separate walls the line `distance` long from `start` along `direction` passes
+/// through. Colliders whose spans along the line overlap or touch are merged into one wall.
+fn walls_crossed(
+    start: Point2<f32>,
+    direction: Vector2<f32>,
+    distance: f32,
+    colliders: &[Aabb2<f32>],
+) -> usize {
+    let mut spans = colliders
+        .iter()
+        .filter_map(|bounds| line_span(start, direction, bounds))
+        .filter(|&(enter, exit)| enter < distance && exit > 0.0)
+        .collect::<Vec<_>>();
+    spans.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+    let mut walls = 0;
+    let mut reached = std::f32::NEG_INFINITY;
+    for (enter, exit) in spans {
+        if enter > reached + WALL_GAP_TOLERANCE {
+            walls += 1;
+        }
+        reached = reached.max(exit);
+    }
+
+    walls
+}
+
+/// How far along the line from `start` along `direction` it enters and leaves `bounds`, or `None`
+/// if it misses them
+fn line_span(
+    start: Point2<f32>,
+    direction: Vector2<f32>,
+    bounds: &Aabb2<f32>,
+) -> Option<(f32, f32)> {
+    let mut enter = std::f32::NEG_INFINITY;
+    let mut exit = std::f32::INFINITY;
+    for axis in 0..2 {
+        if direction[axis].abs() <= std::f32::EPSILON {
+            if start[axis] < bounds.min[axis] || start[axis] > bounds.max[axis] {
+                return None;
+            }
+        } else {
+            let near = (bounds.min[axis] - start[axis]) / direction[axis];
+            let far = (bounds.max[axis] - start[axis]) / direction[axis];
+            enter = enter.max(near.min(far));
+            exit = exit.min(near.max(far));
+        }
+    }
+
+    if enter <= exit {
+        Some((enter, exit))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Aabb2<f32> {
+        Aabb2::new(Point2::new(min_x, min_y), Point2::new(max_x, max_y))
+    }
+
+    #[test]
+    fn touching_colliders_are_one_wall() {
+        let start = Point2::new(0.0, 0.5);
+        let direction = Vector2::new(1.0, 0.0);
+        let colliders = [rect(2.0, 0.0, 3.0, 1.0), rect(3.0, 0.0, 4.0, 1.0)];
+
+        assert_eq!(walls_crossed(start, direction, 10.0, &colliders), 1);
+    }
+
+    #[test]
+    fn separate_colliders_are_separate_walls() {
+        let start = Point2::new(0.0, 0.5);
+        let direction = Vector2::new(1.0, 0.0);
+        let colliders = [
+            rect(2.0, 0.0, 3.0, 1.0),
+            rect(5.0, 0.0, 6.0, 1.0),
+            // Out of the way, and past the end of the line
+            rect(2.0, 3.0, 3.0, 4.0),
+            rect(12.0, 0.0, 13.0, 1.0),
+        ];
+
+        assert_eq!(walls_crossed(start, direction, 10.0, &colliders), 2);
+    }
+}
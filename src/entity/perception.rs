@@ -0,0 +1,137 @@
+use cgmath::{InnerSpace, Point2};
+use collision::Contains;
+use ord_subset::OrdSubsetIterExt;
+use rhusics_ecs::physics2d::DynamicBoundingVolumeTree2;
+use specs::Entity;
+use super::components::GuardAi;
+use super::noise::NoiseEvent;
+use super::resources::{GlobalPlayerState, Shadows, Stance};
+use super::vision::{Sighting, VisionCone, VisionZone};
+
+/// How much slower a guard's suspicion fills when it only sees the player out of the corner of
+/// its eye
+const PERIPHERAL_SUSPICION_RATE: f32 = 0.5;
+/// How much faster a guard's suspicion fills when the player is right next to it
+const NEAR_SUSPICION_RATE: f32 = 3.0;
+/// How much slower a guard's suspicion fills when the player is in the shadows
+const SHADOW_SUSPICION_RATE: f32 = 0.4;
+/// How much slower a guard's suspicion fills when the player is crouching
+const CROUCH_SUSPICION_RATE: f32 = 0.7;
+/// How much slower a guard's suspicion fills when the player is keeping still
+const STILL_SUSPICION_RATE: f32 = 0.6;
+/// How slow the player has to be moving to count as keeping still, in tiles per second
+const STILL_SPEED: f32 = 0.5;
+/// How much faster a guard's suspicion fills once the alarm has been raised
+const ALERT_SUSPICION_RATE: f32 = 1.5;
+/// How much of a guard's suspicion drains away each second while it can't see the player
+const SUSPICION_DECAY_RATE: f32 = 0.15;
+
+/// Everything guards and cameras can sense this frame
+pub struct Senses<'a> {
+    pub tree: &'a DynamicBoundingVolumeTree2<f32>,
+    pub player: &'a GlobalPlayerState,
+    pub shadows: &'a Shadows,
+    /// The noises made since last frame
+    pub noises: &'a [NoiseEvent],
+    /// The ids of the moving bodies, which don't muffle noises like walls do
+    pub bodies: &'a [u32],
+    /// Whether the alarm has been raised, which puts everyone on edge
+    pub alert: bool,
+    pub delta_seconds: f32,
+}
+
+/// What a guard noticed this frame
+pub struct Perception {
+    /// How well the guard can see the player, if it can see them at all
+    pub sighting: Option<Sighting>,
+    /// Where the loudest noise the guard heard came from, and how loud it sounded from 0 to 1
+    pub heard: Option<(Point2<f32>, f32)>,
+}
+
+/// Looks and listens for the player from the given vision cone, updating how suspicious the
+/// guard `viewer` is and where it last saw them
+pub fn perceive(
+    ai: &mut GuardAi,
+    viewer: Entity,
+    cone: &VisionCone,
+    senses: &Senses,
+) -> Perception {
+    let player = senses.player;
+    let mut sighting = cone.can_see(senses.tree, viewer, player.id, &player.bounds);
+
+    // A hidden player can only be seen by guards which watched them hide
+    match player.hiding {
+        Some(spot) => {
+            if ai.saw_player {
+                ai.known_hiding_spot = Some(spot);
+            }
+            if ai.known_hiding_spot != Some(spot) {
+                sighting = None;
+            }
+        }
+        None => ai.known_hiding_spot = None,
+    }
+
+    // Suspicion builds up faster the closer, more exposed, better lit and faster moving the
+    // player is, so a brief glimpse isn't enough to give them away
+    match sighting {
+        Some(sighting) => {
+            let distance = (player.pos - cone.origin).magnitude();
+            let closeness = 1.0 - (distance / cone.range).min(1.0);
+
+            let zone = match sighting.zone {
+                VisionZone::Near => NEAR_SUSPICION_RATE,
+                VisionZone::Focused => 1.0,
+                VisionZone::Peripheral => PERIPHERAL_SUSPICION_RATE,
+            };
+            let in_shadow = senses
+                .shadows
+                .0
+                .iter()
+                .any(|shadow| shadow.contains(&player.pos));
+            let lighting = if in_shadow && !ai.info.flashlight {
+                SHADOW_SUSPICION_RATE
+            } else {
+                1.0
+            };
+            let profile = if player.stance == Stance::Crouching {
+                CROUCH_SUSPICION_RATE
+            } else {
+                1.0
+            };
+            let movement = if player.velocity.magnitude() < STILL_SPEED {
+                STILL_SUSPICION_RATE
+            } else {
+                1.0
+            };
+            let alertness = if senses.alert {
+                ALERT_SUSPICION_RATE
+            } else {
+                1.0
+            };
+
+            let rate = (0.25 + 0.75 * closeness) * sighting.exposure * zone * lighting * profile
+                * movement * alertness / ai.info.reaction_time;
+            ai.suspicion = (ai.suspicion + rate * senses.delta_seconds).min(1.0);
+            ai.last_seen = Some(player.pos);
+            ai.last_seen_velocity = player.velocity;
+            ai.heard_noise = None;
+        }
+        None => {
+            ai.suspicion = (ai.suspicion - SUSPICION_DECAY_RATE * senses.delta_seconds).max(0.0);
+        }
+    }
+
+    // Only the loudest noise is worth going after
+    let heard = senses
+        .noises
+        .iter()
+        .filter_map(|noise| {
+            noise
+                .volume_at(senses.tree, cone.origin, senses.bodies, ai.info.hearing)
+                .map(|volume| (noise.position, volume))
+        })
+        .ord_subset_max_by_key(|&(_position, volume)| volume);
+
+    Perception { sighting, heard }
+}
@@ -1,5 +1,11 @@
-use cgmath::Point2;
-use collision::Aabb2;
+use cgmath::{Point2, Vector2};
+use collision::{Aabb2, Discrete};
+use ggez::graphics::Image;
+use specs::Entity;
+use super::components::Direction;
+
+/// How far below the top of a one way platform a body can have been last frame and still land on it
+const PLATFORM_TOLERANCE: f32 = 0.1;
 
 /// The rectangle of the player. Used in the guard ai.
 pub struct GlobalPlayerState {
@@ -7,16 +13,306 @@ pub struct GlobalPlayerState {
     pub bounds: Aabb2<f32>,
     pub id: u32,
     pub captured: bool,
+    /// The direction the player last moved in
+    pub facing: Direction,
+    /// The index of the hiding spot the player is hiding in, if they are hidden
+    pub hiding: Option<usize>,
+    /// Which way and how fast the player is moving, in tiles per second
+    pub velocity: Vector2<f32>,
+    /// Whether the player is standing, sneaking or crouching
+    pub stance: Stance,
+}
+
+/// How the player is moving around
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Stance {
+    Standing,
+    /// Moving slowly and quietly
+    Sneaking,
+    /// Moving slowly and quietly with a smaller body, which can fit under low gaps and is harder
+    /// to see
+    Crouching,
 }
 
 pub struct GuardJumpBoxes(pub Vec<::level::GuardJumpBox>);
 
 pub struct GuardTurnAroundBoxes(pub Vec<::collision::Aabb2<f32>>);
 
+/// Platforms which can only be stood on from above, and which can be dropped through
+pub struct OneWayPlatforms(pub Vec<::collision::Aabb2<f32>>);
+
+impl OneWayPlatforms {
+    /// The platform a body `half_width` either side of `x` lands on when moving down from
+    /// `last_bottom` to `next_bottom`, if any. Bodies only land on a platform if they were above
+    /// it, so they can still jump up through it from below.
+    pub fn landed_on(
+        &self,
+        x: f32,
+        half_width: f32,
+        last_bottom: f32,
+        next_bottom: f32,
+    ) -> Option<&Aabb2<f32>> {
+        self.0.iter().find(|platform| {
+            x + half_width > platform.min.x
+                && x - half_width < platform.max.x
+                && last_bottom <= platform.min.y + PLATFORM_TOLERANCE
+                && next_bottom >= platform.min.y
+        })
+    }
+}
+
+/// Places the player can hide in. A hidden player can only be seen by guards which watched them
+/// hide, or which have searched their hiding spot.
+pub struct HidingSpots(pub Vec<::collision::Aabb2<f32>>);
+
+/// Dark areas where the guards are slower to notice the player
+pub struct Shadows(pub Vec<::collision::Aabb2<f32>>);
+
+/// Areas the player can climb up and down
+pub struct Ladders(pub Vec<::collision::Aabb2<f32>>);
+
+/// The areas the player escapes through to finish the level
+pub struct Exits(pub Vec<::collision::Aabb2<f32>>);
+
+/// How the player is getting on with the current level
+pub struct LevelProgress {
+    /// How long the level has been played for, in seconds
+    pub time: f32,
+    /// How many times a guard has started chasing the player
+    pub times_spotted: u32,
+    /// How closely the replicas matched the originals on average, from 0 to 1, once they have
+    /// been painted
+    pub accuracy: Option<f32>,
+    /// Whether every painting has been swapped for its replica
+    pub painting_swapped: bool,
+    /// Whether the player has escaped through an exit after swapping the painting
+    pub complete: bool,
+}
+
+impl LevelProgress {
+    pub fn new() -> Self {
+        LevelProgress {
+            time: 0.0,
+            times_spotted: 0,
+            accuracy: None,
+            painting_swapped: false,
+            complete: false,
+        }
+    }
+}
+
+/// How long the alarm stays at alert after the last report before going quiet, in seconds
+const ALERT_TIME: f32 = 30.0;
+/// How long the alarm stays in lockdown after the last report before dropping to alert, in seconds
+const LOCKDOWN_TIME: f32 = 45.0;
+
+/// How alarmed the whole level is
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum AlarmLevel {
+    /// Nobody knows anything is wrong
+    Calm,
+    /// The guards know someone is around, and keep searching for them
+    Alert,
+    /// As alert, but lockdown doors are sealed as well
+    Lockdown,
+}
+
+/// Whether the guards have been alerted that something is wrong. Guards, cameras, tripwires and
+/// forgeries all raise it, and it calms down a level at a time once nothing has been reported for
+/// a while.
+pub struct Alarm {
+    pub level: AlarmLevel,
+    /// Where the player was last reported, if they were seen
+    pub last_known: Option<Point2<f32>>,
+    /// How much longer until the alarm drops a level
+    pub timer: f32,
+    /// Counts up every time something is reported, so guards can tell which reports they have
+    /// already answered
+    pub report: u32,
+}
+
+impl Alarm {
+    pub fn new() -> Self {
+        Alarm {
+            level: AlarmLevel::Calm,
+            last_known: None,
+            timer: 0.0,
+            report: 0,
+        }
+    }
+
+    /// Whether the guards have been alerted at all
+    pub fn is_raised(&self) -> bool {
+        self.level != AlarmLevel::Calm
+    }
+
+    /// Reports the player at `position`, raising the alarm to at least `level` and sending the
+    /// guards there
+    pub fn raise(&mut self, level: AlarmLevel, position: Option<Point2<f32>>) {
+        self.level = self.level.max(level);
+        self.report += 1;
+        self.sighted(position);
+    }
+
+    /// Reports the player at `position`, raising the alarm a level higher than it already is
+    pub fn escalate(&mut self, position: Option<Point2<f32>>) {
+        let level = match self.level {
+            AlarmLevel::Calm => AlarmLevel::Alert,
+            AlarmLevel::Alert | AlarmLevel::Lockdown => AlarmLevel::Lockdown,
+        };
+        self.raise(level, position);
+    }
+
+    /// Keeps track of where the player is while someone can still see them, without sending
+    /// everyone over again. This keeps a raised alarm from calming down.
+    pub fn sighted(&mut self, position: Option<Point2<f32>>) {
+        if position.is_some() {
+            self.last_known = position;
+        }
+        self.timer = match self.level {
+            AlarmLevel::Calm => 0.0,
+            AlarmLevel::Alert => ALERT_TIME,
+            AlarmLevel::Lockdown => LOCKDOWN_TIME,
+        };
+    }
+
+    /// Calms the alarm down a level once its time runs out
+    pub fn update(&mut self, delta_seconds: f32) {
+        if !self.is_raised() {
+            return;
+        }
+
+        self.timer -= delta_seconds;
+        if self.timer <= 0.0 {
+            self.level = match self.level {
+                AlarmLevel::Lockdown => AlarmLevel::Alert,
+                AlarmLevel::Alert | AlarmLevel::Calm => AlarmLevel::Calm,
+            };
+            self.sighted(None);
+            if !self.is_raised() {
+                self.last_known = None;
+            }
+        }
+    }
+}
+
+/// A tripwire read from the level, which raises the alarm when the player walks through it
+pub struct Tripwire {
+    pub bounds: Aabb2<f32>,
+    /// Whether the player is still in the tripwire after setting it off. It won't go off again
+    /// until they have left it.
+    pub tripped: bool,
+}
+
+/// The tripwires in the level
+pub struct Tripwires(pub Vec<Tripwire>);
+
+/// What the player has done with a painting
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PaintingStatus {
+    /// The original is hanging on the wall
+    Hanging,
+    /// The player has taken the original down and is carrying it
+    Carried,
+    /// The replica has been painted, and the player is carrying both
+    Painted,
+    /// The replica is hanging where the original was
+    Swapped,
+}
+
+pub struct PaintingState {
+    pub info: ::level::PaintingInfo,
+    pub original: Image,
+    pub replica: Option<Image>,
+    /// How closely the replica matches the original, from 0 to 1, once it has been painted
+    pub accuracy: Option<f32>,
+    pub status: PaintingStatus,
+}
+
+/// The paintings hanging in the level, and what the player has done with each of them
+pub struct Paintings(pub Vec<PaintingState>);
+
+impl Paintings {
+    /// The painting the player is carrying around, if they are carrying one
+    pub fn carried(&mut self) -> Option<&mut PaintingState> {
+        self.0.iter_mut().find(|painting| {
+            painting.status == PaintingStatus::Carried || painting.status == PaintingStatus::Painted
+        })
+    }
+
+    /// Whether every painting has been swapped for its replica
+    pub fn all_swapped(&self) -> bool {
+        self.0
+            .iter()
+            .all(|painting| painting.status == PaintingStatus::Swapped)
+    }
+}
+
+pub struct Door {
+    pub info: ::level::DoorInfo,
+    pub state: ::level::DoorState,
+    /// Whether the door has been locked by a lockdown, rather than by its key
+    pub sealed: bool,
+    /// The entity holding the door's collision while it is shut
+    pub body: Option<Entity>,
+}
+
+/// The doors in the level. Their collision is added and removed by the level state to match their
+/// state after every update.
+pub struct Doors(pub Vec<Door>);
+
+impl Doors {
+    /// Opens any closed door within `reach` tiles of the given bounds. Locked doors stay shut.
+    pub fn open_near(&mut self, bounds: &Aabb2<f32>, reach: f32) {
+        let reach = Aabb2::new(
+            Point2::new(bounds.min.x - reach, bounds.min.y),
+            Point2::new(bounds.max.x + reach, bounds.max.y),
+        );
+
+        for door in self.0.iter_mut() {
+            if door.state == ::level::DoorState::Closed && door.info.bounds.intersects(&reach) {
+                door.state = ::level::DoorState::Open;
+            }
+        }
+    }
+}
+
+pub struct KeyPickup {
+    pub info: ::level::KeyInfo,
+    pub collected: bool,
+}
+
+/// The keys lying around the level
+pub struct Keys(pub Vec<KeyPickup>);
+
+/// What the player has picked up
+pub struct Inventory {
+    /// The names of the keys the player has collected
+    pub keys: Vec<String>,
+    /// How many more distractions the player can throw
+    pub distractions: u32,
+}
+
+/// How the player is lining up a throw
+pub struct Aim {
+    /// How far above horizontal the player is aiming, in radians
+    pub angle: f32,
+}
+
 #[derive(Clone)]
 pub struct GameInput {
     pub move_horizontal: f32,
     pub jumping: bool,
+    /// Whether the player wants to drop down through the one way platform they're standing on
+    pub dropping: bool,
+    /// Which way the player wants to climb: -1 for up, 1 for down and 0 for neither
+    pub move_vertical: f32,
+    /// Whether the player wants to sneak
+    pub sneaking: bool,
+    /// Whether the player wants to crouch. They stay crouched while there's no room to stand up.
+    pub crouching: bool,
+    /// Whether the player is aiming a throw, which they make once they let go
+    pub aiming: bool,
 }
 
 impl GameInput {
@@ -24,6 +320,11 @@ impl GameInput {
         GameInput {
             move_horizontal: 0.0,
             jumping: false,
+            dropping: false,
+            move_vertical: 0.0,
+            sneaking: false,
+            crouching: false,
+            aiming: false,
         }
     }
 }
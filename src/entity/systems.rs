@@ -1,24 +1,103 @@
 #![allow(unknown_lints)]
 
 use cgmath::{InnerSpace, Point2, Vector2};
-use collision::{dbvt::query_ray, Discrete, Ray2};
+use collision::{dbvt::query_ray, Aabb2, Discrete, Ray2};
 use ggez;
-use ggez::graphics::{self, Color, DrawMode, DrawParam};
+use ggez::graphics::{self, Color, DrawMode, DrawParam, Image};
+use ord_subset::OrdSubsetIterExt;
 use rhusics_core::{ContactEvent, Pose};
 use rhusics_ecs::DeltaTime;
+use rhusics_ecs::collide2d::{CollisionMode, CollisionShape2, CollisionStrategy, Rectangle};
 use rhusics_ecs::physics2d::{BodyPose2, DynamicBoundingVolumeTree2, RigidBodyParts2};
 use shrev::{EventChannel, ReaderId};
 use specs::{Entity, Fetch, FetchMut, Join, ReadStorage, System, WriteStorage};
 use specs::Entities;
 use std;
+use camera::Camera;
+use level::{DoorState, GuardKind, NavEdgeKind, NavGraph};
 use super::components::*;
+use super::distraction::{self, DISTRACTION_SIZE};
+use super::guard_ai::{self, Guard, Surroundings};
+use super::noise::NoiseEvent;
+use super::perception::{perceive, Senses};
 use super::resources::*;
+use super::vision::VisionCone;
 
-/// The acceleration of gravity
-const GRAVITY_ACCEL: f32 = 30.0;
-const GUARD_BASE_SPEED: f32 = 3.0;
-/// How much faster a guard is while alerted (as a multiplier)
-const GUARD_ALERTED_MULTIPLIER: f32 = 1.5;
+/// How long the player falls through one way platforms for after dropping down
+const DROP_THROUGH_TIME: f32 = 0.3;
+/// How much force the player walks with on the ground
+const WALK_FORCE: f32 = 50.0;
+/// How much force the player can move with in the air
+const AIR_WALK_FORCE: f32 = 10.0;
+/// How much slower the player moves while sneaking or crouching
+const SNEAK_SPEED: f32 = 0.5;
+/// How far inside the edges of the player the ceiling is checked for before standing up, in tiles
+const CEILING_INSET: f32 = 0.05;
+/// How much force the player climbs ladders with
+const CLIMB_FORCE: f32 = 30.0;
+/// How far below the top of the player something has to be for them to grab onto it, in tiles
+const LEDGE_GRAB_HEIGHT: f32 = 0.4;
+/// How far past the sides of the player they can reach for a ledge, in tiles
+const LEDGE_REACH: f32 = 0.2;
+/// How much room is left between the player and a ledge they're climbing onto, in tiles
+const LEDGE_CLEARANCE: f32 = 0.05;
+/// How long after letting go of a ledge until the player can grab onto one again
+const LEDGE_GRAB_COOLDOWN: f32 = 0.3;
+/// How hard the player pushes off of a wall when jumping off of it
+const WALL_JUMP_FORCE: f32 = 600.0;
+/// How much of a body's falling speed is taken away each frame while it slides down a wall
+const WALL_SLIDE_FRICTION: f32 = 0.15;
+/// How far above the top of a guard its suspicion meter is drawn, in tiles
+const SUSPICION_METER_OFFSET: f32 = 0.3;
+/// How often the player's footsteps can be heard while running, in seconds
+const FOOTSTEP_INTERVAL: f32 = 0.3;
+/// How fast the player can move without their footsteps making any noise, in tiles per second
+const QUIET_SPEED: f32 = 2.0;
+/// How far the player's footsteps carry for each tile per second they're moving at, in tiles
+const FOOTSTEP_LOUDNESS: f32 = 0.6;
+/// How much quieter the player's footsteps are while sneaking or crouching
+const SNEAK_FOOTSTEP_LOUDNESS: f32 = 0.5;
+/// How far the sound of the player jumping carries, in tiles
+const JUMP_LOUDNESS: f32 = 3.0;
+/// How fast the player has to leave the ground for it to count as a jump, in tiles per second
+const JUMP_SPEED: f32 = 5.0;
+/// How fast the player can fall without making any noise when they land, in tiles per second
+const LANDING_SPEED: f32 = 6.0;
+/// How far the sound of the player landing carries for each tile per second they fell at, in tiles
+const LANDING_LOUDNESS: f32 = 0.5;
+/// How much speed the player has to lose in one frame to count as running into something, in
+/// tiles per second
+const BUMP_SPEED: f32 = 4.0;
+/// How far the sound of the player running into something carries for each tile per second they
+/// were moving at, in tiles
+const BUMP_LOUDNESS: f32 = 0.6;
+/// How far a dog's bark carries, in tiles
+const BARK_LOUDNESS: f32 = 12.0;
+/// How far the noise of a thrown distraction landing carries, in tiles
+const DISTRACTION_LOUDNESS: f32 = 8.0;
+/// How long a thrown distraction lies around after landing, in seconds
+const DISTRACTION_LINGER_TIME: f32 = 4.0;
+/// How far the distractions the player has left are drawn from the corner of the screen, in
+/// logical pixels
+const DISTRACTION_COUNTER_MARGIN: f32 = 6.0;
+/// How far apart the distractions the player has left are drawn, in logical pixels
+const DISTRACTION_COUNTER_SPACING: f32 = 7.0;
+/// How fast the player's aim moves up and down, in radians per second
+const AIM_SPEED: f32 = 1.5;
+/// The lowest the player can aim, in radians above horizontal
+const MIN_AIM_ANGLE: f32 = -0.5;
+/// The highest the player can aim, in radians above horizontal
+const MAX_AIM_ANGLE: f32 = 1.3;
+/// How big a security camera is drawn, in tiles
+const SECURITY_CAMERA_SIZE: f32 = 0.5;
+/// How far away guards can hear another guard radio in a sighting, in tiles
+const RADIO_RANGE: f32 = 15.0;
+/// How thick the border around the screen is while the alarm is raised, in logical pixels
+const ALARM_BORDER_WIDTH: f32 = 3.0;
+/// How close a guard has to be to an unlocked door to open it, in tiles
+const GUARD_DOOR_REACH: f32 = 0.5;
+/// How big the painting the player is carrying is drawn, in tiles
+const CARRIED_PAINTING_SIZE: f32 = 0.75;
 
 pub struct SpriteSystem<'a> {
     ctx: &'a mut ggez::Context,
@@ -34,28 +113,27 @@ impl<'a> System<'a> for SpriteSystem<'a> {
     type SystemData = (
         RigidBodyParts2<'a, f32, BodyPose2<f32>, ()>,
         ReadStorage<'a, Sprite>,
+        Fetch<'a, Camera>,
     );
 
-    fn run(&mut self, (rigid_body_parts, sprite): Self::SystemData) {
+    fn run(&mut self, (rigid_body_parts, sprite, camera): Self::SystemData) {
         for (body, sprite) in (&rigid_body_parts.poses, &sprite).join() {
             let pos = body.position();
+            let screen_pos = camera.to_screen(pos);
             graphics::draw_ex(
                 self.ctx,
                 &sprite.image,
                 DrawParam {
                     src: sprite.clip,
                     dest: graphics::Point2::new(
-                        ((pos.x * 16.0) - sprite.image.width() as f32 / 2.0) * ::GLOBAL_SCALE,
-                        ((pos.y * 16.0) - sprite.image.height() as f32 / 2.0) * ::GLOBAL_SCALE,
+                        screen_pos.x - sprite.image.width() as f32 / 2.0,
+                        screen_pos.y - sprite.image.height() as f32 / 2.0,
                     ),
                     rotation: sprite.rotation,
-                    scale: graphics::Point2::new(
-                        sprite.scale.x * ::GLOBAL_SCALE,
-                        sprite.scale.y * ::GLOBAL_SCALE,
-                    ),
+                    scale: sprite.scale,
                     offset: graphics::Point2::new(0.0, 0.0),
                     shear: graphics::Point2::new(0.0, 0.0),
-                    color: None,
+                    color: sprite.color,
                 },
             ).expect("Error drawing!");
         }
@@ -73,41 +151,443 @@ impl<'a> DebugRenderSystem<'a> {
 }
 
 impl<'a> System<'a> for DebugRenderSystem<'a> {
-    type SystemData = (RigidBodyParts2<'a, f32, BodyPose2<f32>, ()>, );
+    type SystemData = (
+        RigidBodyParts2<'a, f32, BodyPose2<f32>, ()>,
+        ReadStorage<'a, GuardAi>,
+        ReadStorage<'a, Directional>,
+        Fetch<'a, OneWayPlatforms>,
+        Fetch<'a, NavGraph>,
+        Fetch<'a, Camera>,
+    );
+
+    fn run(
+        &mut self,
+        (rigid_body_parts, ai, directional, platforms, nav, camera): Self::SystemData,
+    ) {
+        // The navigation graph, with surfaces in cyan, jumps in magenta and falls in blue
+        for (node, edges) in nav.nodes.iter().zip(nav.edges.iter()) {
+            graphics::set_color(self.ctx, Color::new(0.0, 1.0, 1.0, 0.6))
+                .expect("Error setting color!");
+            graphics::line(
+                self.ctx,
+                &[
+                    camera.to_screen(Point2::new(node.left, node.y)),
+                    camera.to_screen(Point2::new(node.right, node.y)),
+                ],
+                1.0,
+            ).expect("Error drawing nav node!");
+
+            for edge in edges {
+                let color = match edge.kind {
+                    NavEdgeKind::Jump => Color::new(1.0, 0.0, 1.0, 0.6),
+                    NavEdgeKind::Fall => Color::new(0.0, 0.4, 1.0, 0.6),
+                };
+                graphics::set_color(self.ctx, color).expect("Error setting color!");
+                graphics::line(
+                    self.ctx,
+                    &[
+                        camera.to_screen(Point2::new(edge.from_x, node.y)),
+                        camera.to_screen(Point2::new(edge.to_x, nav.nodes[edge.to].y)),
+                    ],
+                    1.0,
+                ).expect("Error drawing nav edge!");
+            }
+        }
+
+        // Vision cones are yellow while the guard can see the player
+        for (ai, directional, pose) in (&ai, &directional, &rigid_body_parts.poses).join() {
+            let color = if ai.saw_player {
+                Color::new(1.0, 1.0, 0.0, 1.0)
+            } else {
+                Color::new(1.0, 1.0, 1.0, 0.4)
+            };
+            graphics::set_color(self.ctx, color).expect("Error setting color!");
+
+            VisionCone::for_guard(ai, pose.position(), directional.direction)
+                .draw(self.ctx, &camera)
+                .expect("Error drawing vision cone!");
+        }
+
+        graphics::set_color(self.ctx, Color::new(0.0, 1.0, 0.0, 1.0))
+            .expect("Error setting color!");
+        for platform in platforms.0.iter() {
+            graphics::line(
+                self.ctx,
+                &[
+                    camera.to_screen(platform.min),
+                    camera.to_screen(Point2::new(platform.max.x, platform.min.y)),
+                ],
+                1.5,
+            ).expect("Error drawing platform!");
+        }
 
-    fn run(&mut self, (rigid_body_parts, ): Self::SystemData) {
         for (shape, pose) in (&rigid_body_parts.shapes, &rigid_body_parts.poses).join() {
             graphics::set_color(self.ctx, Color::new(1.0, 1.0, 1.0, 1.0))
                 .expect("Error setting color!");
-            let pos = pose.position();
+            let pos = camera.to_screen(pose.position());
             let bound = shape.bound();
-            let min = bound.min;
-            let max = bound.max;
-            let final_scale = ::GLOBAL_SCALE * 16.0;
+            let min = camera.to_screen(bound.min);
+            let max = camera.to_screen(bound.max);
             graphics::rectangle(
                 self.ctx,
-                DrawMode::Line(3.0),
-                graphics::Rect::new(
-                    min.x * final_scale,
-                    min.y * final_scale,
-                    (max.x - min.x) * final_scale,
-                    (max.y - min.y) * final_scale,
-                ),
+                DrawMode::Line(1.5),
+                graphics::Rect::new(min.x, min.y, max.x - min.x, max.y - min.y),
             ).expect("Error drawing entity bounds!");
 
             graphics::set_color(self.ctx, Color::new(1.0, 0.0, 0.0, 1.0))
                 .expect("Error setting color!");
-            let draw_x = pos.x * final_scale;
-            let draw_y = pos.y * final_scale;
             graphics::rectangle(
                 self.ctx,
                 DrawMode::Fill,
-                graphics::Rect::new(draw_x - 2.0, draw_y - 2.0, 4.0, 4.0),
+                graphics::Rect::new(pos.x - 1.0, pos.y - 1.0, 2.0, 2.0),
             ).expect("Error drawing entity origin!");
         }
     }
 }
 
+/// Draws the paintings hanging on the walls, and the one the player is carrying
+pub struct PaintingRenderSystem<'a> {
+    ctx: &'a mut ggez::Context,
+}
+
+impl<'a> PaintingRenderSystem<'a> {
+    pub fn new(ctx: &'a mut ggez::Context) -> Self {
+        PaintingRenderSystem { ctx }
+    }
+
+    fn draw_painting(&mut self, image: &Image, min: graphics::Point2, max: graphics::Point2) {
+        graphics::set_color(self.ctx, Color::new(0.35, 0.2, 0.1, 1.0))
+            .expect("Error setting color!");
+        graphics::rectangle(
+            self.ctx,
+            DrawMode::Fill,
+            graphics::Rect::new(min.x - 1.0, min.y - 1.0, max.x - min.x + 2.0, max.y - min.y + 2.0),
+        ).expect("Error drawing painting frame!");
+        graphics::set_color(self.ctx, Color::new(1.0, 1.0, 1.0, 1.0))
+            .expect("Error setting color!");
+
+        graphics::draw_ex(
+            self.ctx,
+            image,
+            DrawParam {
+                src: graphics::Rect::one(),
+                dest: min,
+                rotation: 0.0,
+                scale: graphics::Point2::new(
+                    (max.x - min.x) / image.width() as f32,
+                    (max.y - min.y) / image.height() as f32,
+                ),
+                offset: graphics::Point2::new(0.0, 0.0),
+                shear: graphics::Point2::new(0.0, 0.0),
+                color: None,
+            },
+        ).expect("Error drawing painting!");
+    }
+}
+
+impl<'a> System<'a> for PaintingRenderSystem<'a> {
+    type SystemData = (
+        Fetch<'a, Paintings>,
+        Fetch<'a, GlobalPlayerState>,
+        Fetch<'a, Camera>,
+    );
+
+    fn run(&mut self, (paintings, player_state, camera): Self::SystemData) {
+        for painting in paintings.0.iter() {
+            let min = camera.to_screen(painting.info.bounds.min);
+            let max = camera.to_screen(painting.info.bounds.max);
+
+            match painting.status {
+                PaintingStatus::Hanging => self.draw_painting(&painting.original, min, max),
+                PaintingStatus::Swapped => self.draw_painting(
+                    painting.replica.as_ref().unwrap_or(&painting.original),
+                    min,
+                    max,
+                ),
+                PaintingStatus::Carried | PaintingStatus::Painted => {
+                    // A faded patch on the wall where the painting used to hang
+                    graphics::set_color(self.ctx, Color::new(0.0, 0.0, 0.0, 0.25))
+                        .expect("Error setting color!");
+                    graphics::rectangle(
+                        self.ctx,
+                        DrawMode::Fill,
+                        graphics::Rect::new(min.x, min.y, max.x - min.x, max.y - min.y),
+                    ).expect("Error drawing painting spot!");
+                    graphics::set_color(self.ctx, Color::new(1.0, 1.0, 1.0, 1.0))
+                        .expect("Error setting color!");
+
+                    // The painting being carried is held above the player's head
+                    let centre_x = (player_state.bounds.min.x + player_state.bounds.max.x) / 2.0;
+                    let top = player_state.bounds.min.y - CARRIED_PAINTING_SIZE - 0.1;
+                    let min = camera.to_screen(Point2::new(
+                        centre_x - CARRIED_PAINTING_SIZE / 2.0,
+                        top,
+                    ));
+                    let max = camera.to_screen(Point2::new(
+                        centre_x + CARRIED_PAINTING_SIZE / 2.0,
+                        top + CARRIED_PAINTING_SIZE,
+                    ));
+                    self.draw_painting(&painting.original, min, max);
+                }
+            }
+        }
+    }
+}
+
+/// Draws the doors, the keys which haven't been picked up yet and the tripwires
+pub struct DoorRenderSystem<'a> {
+    ctx: &'a mut ggez::Context,
+}
+
+impl<'a> DoorRenderSystem<'a> {
+    pub fn new(ctx: &'a mut ggez::Context) -> Self {
+        DoorRenderSystem { ctx }
+    }
+}
+
+impl<'a> System<'a> for DoorRenderSystem<'a> {
+    type SystemData = (
+        Fetch<'a, Doors>,
+        Fetch<'a, Keys>,
+        Fetch<'a, Tripwires>,
+        Fetch<'a, Camera>,
+    );
+
+    fn run(&mut self, (doors, keys, tripwires, camera): Self::SystemData) {
+        for door in doors.0.iter() {
+            let min = camera.to_screen(door.info.bounds.min);
+            let max = camera.to_screen(door.info.bounds.max);
+            let rect = graphics::Rect::new(min.x, min.y, max.x - min.x, max.y - min.y);
+
+            let (color, mode) = match door.state {
+                DoorState::Open => (Color::new(0.45, 0.3, 0.15, 1.0), DrawMode::Line(1.0)),
+                DoorState::Closed => (Color::new(0.45, 0.3, 0.15, 1.0), DrawMode::Fill),
+                DoorState::Locked if door.sealed => {
+                    (Color::new(0.55, 0.15, 0.15, 1.0), DrawMode::Fill)
+                }
+                DoorState::Locked => (Color::new(0.3, 0.3, 0.35, 1.0), DrawMode::Fill),
+            };
+
+            graphics::set_color(self.ctx, color).expect("Error setting color!");
+            graphics::rectangle(self.ctx, mode, rect).expect("Error drawing door!");
+        }
+
+        graphics::set_color(self.ctx, Color::new(1.0, 0.85, 0.2, 1.0))
+            .expect("Error setting color!");
+        for key in keys.0.iter().filter(|key| !key.collected) {
+            let min = camera.to_screen(key.info.bounds.min);
+            let max = camera.to_screen(key.info.bounds.max);
+            graphics::rectangle(
+                self.ctx,
+                DrawMode::Fill,
+                graphics::Rect::new(min.x, min.y, max.x - min.x, max.y - min.y),
+            ).expect("Error drawing key!");
+        }
+
+        // Tripwires are drawn as a thin beam across the middle of their area
+        for tripwire in tripwires.0.iter() {
+            let (color, width) = if tripwire.tripped {
+                (Color::new(1.0, 0.2, 0.2, 0.9), 2.0)
+            } else {
+                (Color::new(1.0, 0.2, 0.2, 0.5), 1.0)
+            };
+            let bounds = tripwire.bounds;
+            let (start, end) = if bounds.max.x - bounds.min.x >= bounds.max.y - bounds.min.y {
+                let y = (bounds.min.y + bounds.max.y) / 2.0;
+                (Point2::new(bounds.min.x, y), Point2::new(bounds.max.x, y))
+            } else {
+                let x = (bounds.min.x + bounds.max.x) / 2.0;
+                (Point2::new(x, bounds.min.y), Point2::new(x, bounds.max.y))
+            };
+
+            graphics::set_color(self.ctx, color).expect("Error setting color!");
+            graphics::line(
+                self.ctx,
+                &[camera.to_screen(start), camera.to_screen(end)],
+                width,
+            ).expect("Error drawing tripwire!");
+        }
+
+        graphics::set_color(self.ctx, Color::new(1.0, 1.0, 1.0, 1.0))
+            .expect("Error setting color!");
+    }
+}
+
+/// Shades the shadows and hiding spots. Hiding spots are darker when the player is hidden in one.
+pub struct ShadowRenderSystem<'a> {
+    ctx: &'a mut ggez::Context,
+}
+
+impl<'a> ShadowRenderSystem<'a> {
+    pub fn new(ctx: &'a mut ggez::Context) -> Self {
+        ShadowRenderSystem { ctx }
+    }
+}
+
+impl<'a> System<'a> for ShadowRenderSystem<'a> {
+    type SystemData = (
+        Fetch<'a, Shadows>,
+        Fetch<'a, HidingSpots>,
+        Fetch<'a, GlobalPlayerState>,
+        Fetch<'a, Camera>,
+    );
+
+    fn run(&mut self, (shadows, hiding_spots, player_state, camera): Self::SystemData) {
+        graphics::set_color(self.ctx, Color::new(0.0, 0.0, 0.1, 0.3))
+            .expect("Error setting color!");
+        for shadow in shadows.0.iter() {
+            let min = camera.to_screen(shadow.min);
+            let max = camera.to_screen(shadow.max);
+            graphics::rectangle(
+                self.ctx,
+                DrawMode::Fill,
+                graphics::Rect::new(min.x, min.y, max.x - min.x, max.y - min.y),
+            ).expect("Error drawing shadow!");
+        }
+
+        for (index, spot) in hiding_spots.0.iter().enumerate() {
+            let min = camera.to_screen(spot.min);
+            let max = camera.to_screen(spot.max);
+
+            let alpha = if player_state.hiding == Some(index) { 0.75 } else { 0.35 };
+            graphics::set_color(self.ctx, Color::new(0.1, 0.05, 0.15, alpha))
+                .expect("Error setting color!");
+            graphics::rectangle(
+                self.ctx,
+                DrawMode::Fill,
+                graphics::Rect::new(min.x, min.y, max.x - min.x, max.y - min.y),
+            ).expect("Error drawing hiding spot!");
+        }
+
+        graphics::set_color(self.ctx, Color::new(1.0, 1.0, 1.0, 1.0))
+            .expect("Error setting color!");
+    }
+}
+
+/// Draws the security cameras, and the light from them and from guards' flashlights
+pub struct LightRenderSystem<'a> {
+    ctx: &'a mut ggez::Context,
+}
+
+impl<'a> LightRenderSystem<'a> {
+    pub fn new(ctx: &'a mut ggez::Context) -> Self {
+        LightRenderSystem { ctx }
+    }
+}
+
+impl<'a> System<'a> for LightRenderSystem<'a> {
+    type SystemData = (
+        ReadStorage<'a, GuardAi>,
+        ReadStorage<'a, Directional>,
+        RigidBodyParts2<'a, f32, BodyPose2<f32>, ()>,
+        Fetch<'a, Camera>,
+    );
+
+    fn run(&mut self, (ai, directional, rigid_body_parts, camera): Self::SystemData) {
+        for (ai, directional, pose) in (&ai, &directional, &rigid_body_parts.poses).join() {
+            let is_camera = ai.info.kind == GuardKind::Camera;
+            if !is_camera && !ai.info.flashlight {
+                continue;
+            }
+
+            // Light turns red once it has fallen on the player
+            let color = if ai.saw_player {
+                Color::new(1.0, 0.2, 0.1, 0.2)
+            } else {
+                Color::new(1.0, 1.0, 0.6, 0.15)
+            };
+            graphics::set_color(self.ctx, color).expect("Error setting color!");
+            VisionCone::for_guard(ai, pose.position(), directional.direction)
+                .fill(self.ctx, &camera)
+                .expect("Error drawing light!");
+
+            if is_camera {
+                let position = pose.position();
+                let min = camera.to_screen(Point2::new(
+                    position.x - SECURITY_CAMERA_SIZE / 2.0,
+                    position.y - SECURITY_CAMERA_SIZE / 2.0,
+                ));
+                let size = SECURITY_CAMERA_SIZE * 16.0;
+
+                graphics::set_color(self.ctx, Color::new(0.25, 0.25, 0.3, 1.0))
+                    .expect("Error setting color!");
+                graphics::rectangle(
+                    self.ctx,
+                    DrawMode::Fill,
+                    graphics::Rect::new(min.x, min.y, size, size),
+                ).expect("Error drawing security camera!");
+            }
+        }
+
+        graphics::set_color(self.ctx, Color::new(1.0, 1.0, 1.0, 1.0))
+            .expect("Error setting color!");
+    }
+}
+
+/// Draws a meter above each guard showing how suspicious it is
+pub struct SuspicionRenderSystem<'a> {
+    ctx: &'a mut ggez::Context,
+}
+
+impl<'a> SuspicionRenderSystem<'a> {
+    pub fn new(ctx: &'a mut ggez::Context) -> Self {
+        SuspicionRenderSystem { ctx }
+    }
+}
+
+impl<'a> System<'a> for SuspicionRenderSystem<'a> {
+    type SystemData = (
+        ReadStorage<'a, GuardAi>,
+        RigidBodyParts2<'a, f32, BodyPose2<f32>, ()>,
+        Fetch<'a, Camera>,
+    );
+
+    fn run(&mut self, (ai, rigid_body_parts, camera): Self::SystemData) {
+        for (ai, pose) in (&ai, &rigid_body_parts.poses).join() {
+            if ai.suspicion <= std::f32::EPSILON && ai.state == GuardAiState::Patrolling {
+                continue;
+            }
+
+            let size = match ai.info.kind {
+                GuardKind::Camera => (SECURITY_CAMERA_SIZE, SECURITY_CAMERA_SIZE),
+                _ => ::GUARD_SIZE,
+            };
+            let position = pose.position();
+            let min = camera.to_screen(Point2::new(
+                position.x - size.0 / 2.0,
+                position.y - size.1 / 2.0 - SUSPICION_METER_OFFSET,
+            ));
+            let width = size.0 * 16.0;
+
+            let fill = match ai.state {
+                GuardAiState::Patrolling | GuardAiState::Suspicious => {
+                    Color::new(1.0, 0.85, 0.0, 1.0)
+                }
+                GuardAiState::Searching => Color::new(1.0, 0.5, 0.0, 1.0),
+                GuardAiState::Chasing => Color::new(1.0, 0.0, 0.0, 1.0),
+            };
+
+            graphics::set_color(self.ctx, Color::new(0.0, 0.0, 0.0, 0.6))
+                .expect("Error setting color!");
+            graphics::rectangle(
+                self.ctx,
+                DrawMode::Fill,
+                graphics::Rect::new(min.x - 1.0, min.y - 1.0, width + 2.0, 4.0),
+            ).expect("Error drawing suspicion meter!");
+
+            graphics::set_color(self.ctx, fill).expect("Error setting color!");
+            graphics::rectangle(
+                self.ctx,
+                DrawMode::Fill,
+                graphics::Rect::new(min.x, min.y, width * ai.suspicion, 2.0),
+            ).expect("Error drawing suspicion meter!");
+        }
+
+        graphics::set_color(self.ctx, Color::new(1.0, 1.0, 1.0, 1.0))
+            .expect("Error setting color!");
+    }
+}
+
 /// Updates the player's rectangle
 pub struct CollisionStateSystem {
     pub contact_reader: ReaderId<ContactEvent<Entity, Point2<f32>>>,
@@ -123,15 +603,27 @@ impl<'a> System<'a> for CollisionStateSystem {
 
     fn run(&mut self, (mut collision_state, contacts, delta_time): Self::SystemData) {
         for (mut collision_state, ) in (&mut collision_state, ).join() {
-            collision_state.ground = false;
+            collision_state.ground = collision_state.on_platform;
             if collision_state.jump_cooldown > 0.0 {
                 collision_state.jump_cooldown = (collision_state.jump_cooldown - delta_time.delta_seconds).max(0.0);
             }
+            if collision_state.drop_through > 0.0 {
+                collision_state.drop_through = (collision_state.drop_through - delta_time.delta_seconds).max(0.0);
+            }
+            if collision_state.grab_cooldown > 0.0 {
+                collision_state.grab_cooldown = (collision_state.grab_cooldown - delta_time.delta_seconds).max(0.0);
+            }
+            collision_state.wall = None;
         }
         for event in contacts.read(&mut self.contact_reader) {
             if let Some(state) = collision_state.get_mut(event.bodies.0) {
-                if event.contact.normal.dot(Vector2::new(0.0, 1.0)) > 0.25 {
+                let normal = event.contact.normal;
+                if normal.dot(Vector2::new(0.0, 1.0)) > 0.25 {
                     state.ground = true;
+                } else if normal.x > 0.75 {
+                    state.wall = Some(Direction::Right);
+                } else if normal.x < -0.75 {
+                    state.wall = Some(Direction::Left);
                 }
             }
         }
@@ -150,6 +642,9 @@ impl<'a> System<'a> for PlayerSystem {
         RigidBodyParts2<'a, f32, BodyPose2<f32>, ()>,
         FetchMut<'a, GlobalPlayerState>,
         Fetch<'a, GameInput>,
+        Fetch<'a, HidingSpots>,
+        Fetch<'a, Ladders>,
+        Fetch<'a, DynamicBoundingVolumeTree2<f32>>,
     );
 
     fn run(
@@ -161,34 +656,410 @@ impl<'a> System<'a> for PlayerSystem {
             mut rigid_body_parts,
             mut player_state,
             input,
+            hiding_spots,
+            ladders,
+            tree,
         ): Self::SystemData,
     ) {
         // FIXME player isn't used?
-        for (entity, _player, mut collision_state, body, shape, mut forces) in (
+        for (
+            entity,
+            _player,
+            mut collision_state,
+            body,
+            next_body,
+            shape,
+            velocity,
+            next_velocity,
+            mut forces,
+        ) in (
             &*entities,
             &player,
             &mut collision_state,
-            &rigid_body_parts.poses,
-            &rigid_body_parts.shapes,
+            &mut rigid_body_parts.poses,
+            &mut rigid_body_parts.next_poses,
+            &mut rigid_body_parts.shapes,
+            &mut rigid_body_parts.velocities,
+            &mut rigid_body_parts.next_velocities,
             &mut rigid_body_parts.forces,
         ).join()
             {
                 let pos = body.position();
+                player_state.velocity = *velocity.linear();
                 player_state.pos = pos;
                 player_state.bounds = shape.bound().clone();
                 player_state.id = entity.id();
 
-                let walk_speed = if collision_state.ground { 50.0 } else { 10.0 };
-                forces.add_force(Vector2::new(input.move_horizontal * walk_speed, 0.0)); // TODO: constant for speed
+                // Stop hiding if the player has been pushed out of their hiding spot
+                if let Some(spot) = player_state.hiding {
+                    let inside = hiding_spots
+                        .0
+                        .get(spot)
+                        .map_or(false, |spot| spot.intersects(&player_state.bounds));
+                    if !inside {
+                        player_state.hiding = None;
+                    }
+                }
+
+                // The player keeps still while hidden
+                if player_state.hiding.is_some() {
+                    continue;
+                }
+
+                if input.move_horizontal > 0.0 {
+                    player_state.facing = Direction::Right;
+                } else if input.move_horizontal < 0.0 {
+                    player_state.facing = Direction::Left;
+                }
+
+                // The player stands still while lining up a throw, turning with left and right and
+                // aiming with up and down
+                if input.aiming {
+                    continue;
+                }
+
+                // Stay crouched while there's no room to stand up
+                let crouched = player_state.stance == Stance::Crouching;
+                let blocked = crouched && ceiling_above(&*tree, entity.id(), &player_state.bounds);
+                player_state.stance = if input.crouching || blocked {
+                    Stance::Crouching
+                } else if input.sneaking {
+                    Stance::Sneaking
+                } else {
+                    Stance::Standing
+                };
+
+                let size = if player_state.stance == Stance::Crouching {
+                    ::PLAYER_CROUCH_SIZE
+                } else {
+                    ::PLAYER_SIZE
+                };
+                if size != collision_state.size {
+                    // Keep the player's feet where they are as their body changes height
+                    let shift = Vector2::new(0.0, (collision_state.size.1 - size.1) / 2.0);
+                    body.set_position(pos + shift);
+                    let next_pos = next_body.value.position();
+                    next_body.value.set_position(next_pos + shift);
+
+                    *shape = CollisionShape2::<f32, BodyPose2<f32>, ()>::new_simple(
+                        CollisionStrategy::FullResolution,
+                        CollisionMode::Discrete,
+                        Rectangle::new(size.0, size.1).into(),
+                    );
+                    collision_state.size = size;
+                }
+
+                collision_state.wall_sliding = false;
+
+                // Hang from a ledge until the player pulls themselves up onto it or lets go
+                if let Some(corner) = collision_state.hanging {
+                    let side = (corner.x - pos.x).signum();
+                    velocity.set_linear(Vector2::new(0.0, 0.0));
+                    next_velocity.value.set_linear(Vector2::new(0.0, 0.0));
+
+                    if input.jumping {
+                        let (half_width, half_height) =
+                            (collision_state.size.0 / 2.0, collision_state.size.1 / 2.0);
+                        let on_ledge = Point2::new(
+                            corner.x + side * (half_width + LEDGE_CLEARANCE),
+                            corner.y - half_height - LEDGE_CLEARANCE,
+                        );
+                        body.set_position(on_ledge);
+                        next_body.value.set_position(on_ledge);
+                        collision_state.hanging = None;
+                        collision_state.jump_cooldown = 0.25;
+                    } else if input.move_vertical > 0.0 || input.move_horizontal * side < 0.0 {
+                        collision_state.hanging = None;
+                        collision_state.grab_cooldown = LEDGE_GRAB_COOLDOWN;
+                    }
+                    continue;
+                }
 
-                if collision_state.ground && input.jumping && collision_state.jump_cooldown <= std::f32::EPSILON {
-                    forces.add_force(Vector2::new(0.0, -1000.0));
+                // Climb any ladder the player is on once they press up or down
+                let on_ladder = ladders
+                    .0
+                    .iter()
+                    .any(|ladder| ladder.intersects(&player_state.bounds));
+                let standing_on_floor = collision_state.ground && !collision_state.on_platform;
+                if !on_ladder {
+                    collision_state.climbing = false;
+                } else if input.move_vertical < 0.0
+                    || (input.move_vertical > 0.0 && !standing_on_floor)
+                {
+                    collision_state.climbing = true;
+                } else if standing_on_floor {
+                    collision_state.climbing = false;
+                }
+
+                if collision_state.climbing {
+                    // Climb down through any one way platforms the ladder passes through
+                    if input.move_vertical > 0.0 {
+                        collision_state.drop_through = DROP_THROUGH_TIME;
+                    }
+                    forces.add_force(Vector2::new(
+                        input.move_horizontal * CLIMB_FORCE * SNEAK_SPEED,
+                        input.move_vertical * CLIMB_FORCE,
+                    ));
+                    continue;
+                }
+
+                // Grab onto ledges, slide down walls and jump off of them while pushing against
+                // a wall in the air
+                let falling = velocity.linear().y > 0.0;
+                if let Some(wall) = collision_state.wall {
+                    let pushing = input.move_horizontal * wall.multiplier() > 0.0;
+                    if !collision_state.ground && pushing {
+                        let ledge = if falling && collision_state.grab_cooldown <= 0.0 {
+                            find_ledge(&*tree, entity.id(), &player_state.bounds, wall)
+                        } else {
+                            None
+                        };
+
+                        if let Some(corner) = ledge {
+                            let hanging = Point2::new(pos.x, corner.y + collision_state.size.1 / 2.0);
+                            body.set_position(hanging);
+                            next_body.value.set_position(hanging);
+                            velocity.set_linear(Vector2::new(0.0, 0.0));
+                            next_velocity.value.set_linear(Vector2::new(0.0, 0.0));
+                            collision_state.hanging = Some(corner);
+                            continue;
+                        }
+
+                        collision_state.wall_sliding = falling;
+
+                        if input.jumping && collision_state.jump_cooldown <= std::f32::EPSILON {
+                            let linear = *velocity.linear();
+                            velocity.set_linear(Vector2::new(linear.x, 0.0));
+                            forces.add_force(Vector2::new(
+                                -wall.multiplier() * WALL_JUMP_FORCE,
+                                -::JUMP_FORCE,
+                            ));
+                            collision_state.jump_cooldown = 0.25;
+                            continue;
+                        }
+                    }
+                }
+
+                let walk_speed = if collision_state.ground { WALK_FORCE } else { AIR_WALK_FORCE };
+                let walk_speed = match player_state.stance {
+                    Stance::Standing => walk_speed,
+                    Stance::Sneaking | Stance::Crouching => walk_speed * SNEAK_SPEED,
+                };
+                forces.add_force(Vector2::new(input.move_horizontal * walk_speed, 0.0));
+
+                if collision_state.on_platform && input.dropping {
+                    collision_state.drop_through = DROP_THROUGH_TIME;
+                } else if collision_state.ground && input.jumping && collision_state.jump_cooldown <= std::f32::EPSILON {
+                    forces.add_force(Vector2::new(0.0, -::JUMP_FORCE));
                     collision_state.jump_cooldown = 0.25;
                 }
             }
     }
 }
 
+/// How far a ray from `origin` goes before hitting anything other than the player with the given
+/// id, if it hits anything. `direction` has to be a unit vector.
+fn ray_distance(
+    tree: &DynamicBoundingVolumeTree2<f32>,
+    player: u32,
+    origin: Point2<f32>,
+    direction: Vector2<f32>,
+) -> Option<f32> {
+    query_ray(tree, Ray2::new(origin, direction))
+        .into_iter()
+        .filter(|&(hit, _point)| hit.value.id() != player)
+        .map(|(_hit, point)| (point - origin).dot(direction))
+        .ord_subset_min()
+}
+
+/// Whether there's anything in the way of the crouching player with the given id and bounds
+/// standing back up
+fn ceiling_above(tree: &DynamicBoundingVolumeTree2<f32>, player: u32, bounds: &Aabb2<f32>) -> bool {
+    let headroom = ::PLAYER_SIZE.1 - (bounds.max.y - bounds.min.y);
+
+    [bounds.min.x + CEILING_INSET, bounds.max.x - CEILING_INSET]
+        .iter()
+        .any(|&x| {
+            let top = Point2::new(x, bounds.min.y);
+            ray_distance(tree, player, top, Vector2::new(0.0, -1.0))
+                .map_or(false, |distance| distance < headroom)
+        })
+}
+
+/// The corner of a ledge the player with the given id and bounds can grab onto from the wall on
+/// the given side of them, if there is one. A ledge is a wall in reach of the player's hands with
+/// nothing in reach just above their head.
+fn find_ledge(
+    tree: &DynamicBoundingVolumeTree2<f32>,
+    player: u32,
+    bounds: &Aabb2<f32>,
+    wall: Direction,
+) -> Option<Point2<f32>> {
+    let side = Vector2::new(wall.multiplier(), 0.0);
+    let centre_x = bounds.min.x + (bounds.max.x - bounds.min.x) / 2.0;
+    let reach = (bounds.max.x - bounds.min.x) / 2.0 + LEDGE_REACH;
+
+    let hands = Point2::new(centre_x, bounds.min.y + LEDGE_GRAB_HEIGHT);
+    let to_wall = match ray_distance(tree, player, hands, side) {
+        Some(distance) if distance <= reach => distance,
+        _ => return None,
+    };
+
+    let above = Point2::new(centre_x, bounds.min.y - LEDGE_CLEARANCE);
+    if ray_distance(tree, player, above, side).map_or(false, |distance| distance <= reach) {
+        return None;
+    }
+
+    // Find the top of the wall from just past its face
+    let face_x = centre_x + wall.multiplier() * to_wall;
+    let probe = Point2::new(face_x + wall.multiplier() * LEDGE_CLEARANCE, above.y);
+    let top = probe.y + ray_distance(tree, player, probe, Vector2::new(0.0, 1.0))?;
+
+    if top <= hands.y {
+        Some(Point2::new(face_x, top))
+    } else {
+        None
+    }
+}
+
+/// Makes noise as the player moves around: footsteps while running, jumping, landing from a height
+/// and running into things
+pub struct PlayerNoiseSystem {
+    /// Whether the player was on the ground last frame
+    was_grounded: bool,
+    /// How fast the player was moving last frame
+    last_velocity: Vector2<f32>,
+    /// How long until the player's next footstep can be heard
+    footstep_timer: f32,
+}
+
+impl PlayerNoiseSystem {
+    pub fn new() -> Self {
+        PlayerNoiseSystem {
+            was_grounded: false,
+            last_velocity: Vector2::new(0.0, 0.0),
+            footstep_timer: 0.0,
+        }
+    }
+}
+
+impl<'a> System<'a> for PlayerNoiseSystem {
+    type SystemData = (
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, CollisionState>,
+        RigidBodyParts2<'a, f32, BodyPose2<f32>, ()>,
+        Fetch<'a, GlobalPlayerState>,
+        Fetch<'a, DeltaTime<f32>>,
+        FetchMut<'a, EventChannel<NoiseEvent>>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            player,
+            collision_state,
+            rigid_body_parts,
+            player_state,
+            delta_time,
+            mut noises,
+        ): Self::SystemData,
+    ) {
+        for (_player, collision_state, pose, velocity) in (
+            &player,
+            &collision_state,
+            &rigid_body_parts.poses,
+            &rigid_body_parts.velocities,
+        ).join()
+        {
+            let velocity = *velocity.linear();
+            let mut loudness = Vec::new();
+
+            if collision_state.ground {
+                if !self.was_grounded && self.last_velocity.y > LANDING_SPEED {
+                    loudness.push(self.last_velocity.y * LANDING_LOUDNESS);
+                }
+
+                let speed = velocity.x.abs();
+                if speed > QUIET_SPEED {
+                    self.footstep_timer -= delta_time.delta_seconds;
+                    if self.footstep_timer <= 0.0 {
+                        let quietness = match player_state.stance {
+                            Stance::Standing => 1.0,
+                            Stance::Sneaking | Stance::Crouching => SNEAK_FOOTSTEP_LOUDNESS,
+                        };
+                        loudness.push(speed * FOOTSTEP_LOUDNESS * quietness);
+                        self.footstep_timer = FOOTSTEP_INTERVAL;
+                    }
+                } else {
+                    self.footstep_timer = 0.0;
+                }
+            } else if self.was_grounded && velocity.y < -JUMP_SPEED {
+                loudness.push(JUMP_LOUDNESS);
+            }
+
+            if self.last_velocity.x.abs() - velocity.x.abs() > BUMP_SPEED {
+                loudness.push(self.last_velocity.x.abs() * BUMP_LOUDNESS);
+            }
+
+            for loudness in loudness {
+                noises.single_write(NoiseEvent {
+                    position: pose.position(),
+                    loudness,
+                });
+            }
+
+            self.was_grounded = collision_state.ground;
+            self.last_velocity = velocity;
+        }
+    }
+}
+
+/// Stops bodies falling through one way platforms. This runs after the contact resolution and
+/// corrects the next frame's pose, so the platforms never have to be part of the collision world.
+pub struct OneWayPlatformSystem;
+
+impl<'a> System<'a> for OneWayPlatformSystem {
+    type SystemData = (
+        WriteStorage<'a, CollisionState>,
+        RigidBodyParts2<'a, f32, BodyPose2<f32>, ()>,
+        Fetch<'a, OneWayPlatforms>,
+    );
+
+    fn run(&mut self, (mut collision_state, mut rigid_body_parts, platforms): Self::SystemData) {
+        for (mut collision_state, pose, mut next_pose, mut next_velocity) in (
+            &mut collision_state,
+            &rigid_body_parts.poses,
+            &mut rigid_body_parts.next_poses,
+            &mut rigid_body_parts.next_velocities,
+        ).join()
+            {
+                collision_state.on_platform = false;
+
+                let velocity = next_velocity.value.linear();
+                if collision_state.drop_through > 0.0 || velocity.y < 0.0 {
+                    continue;
+                }
+
+                let half_width = collision_state.size.0 / 2.0;
+                let half_height = collision_state.size.1 / 2.0;
+                let last_bottom = pose.position().y + half_height;
+                let next_position = next_pose.value.position();
+                let next_bottom = next_position.y + half_height;
+
+                let landed =
+                    platforms.landed_on(next_position.x, half_width, last_bottom, next_bottom);
+
+                if let Some(platform) = landed {
+                    next_pose
+                        .value
+                        .set_position(Point2::new(next_position.x, platform.min.y - half_height));
+                    next_velocity.value.set_linear(Vector2::new(velocity.x, 0.0));
+                    collision_state.on_platform = true;
+                }
+            }
+    }
+}
+
 pub struct PhysicsExtras;
 
 impl<'a> System<'a> for PhysicsExtras {
@@ -207,21 +1078,135 @@ impl<'a> System<'a> for PhysicsExtras {
         ).join()
             {
                 let mass_value = mass.mass();
-                forces.add_force(Vector2::new(0.0, GRAVITY_ACCEL * mass_value)); // TODO: constant for speed
 
-                let friction =
-                    if collision_state.ground { 0.1 } else { 0.02 } / delta_time.delta_seconds;
+                // Bodies climbing ladders or hanging from ledges hold themselves up
+                let held_up = collision_state.climbing || collision_state.hanging.is_some();
+                if !held_up {
+                    forces.add_force(Vector2::new(0.0, ::GRAVITY_ACCEL * mass_value));
+                }
+
+                let friction = if collision_state.ground || held_up {
+                    ::GROUND_FRICTION
+                } else {
+                    ::AIR_FRICTION
+                } / delta_time.delta_seconds;
+                let vertical_friction = if collision_state.wall_sliding {
+                    WALL_SLIDE_FRICTION / delta_time.delta_seconds
+                } else {
+                    friction
+                };
 
                 let linear = velocity.linear();
                 forces.add_force(Vector2::new(
                     -linear.x * friction * mass_value,
-                    -linear.y * friction * mass_value,
+                    -linear.y * vertical_friction * mass_value,
                 ));
             }
     }
 }
 
-pub struct GuardAiSystem;
+/// Moves the camera to follow the player
+pub struct CameraSystem;
+
+impl<'a> System<'a> for CameraSystem {
+    type SystemData = (
+        FetchMut<'a, Camera>,
+        Fetch<'a, GlobalPlayerState>,
+        Fetch<'a, DeltaTime<f32>>,
+    );
+
+    fn run(&mut self, (mut camera, player_state, delta_time): Self::SystemData) {
+        camera.follow(
+            player_state.pos,
+            player_state.facing.multiplier(),
+            delta_time.delta_seconds,
+        );
+    }
+}
+
+/// Picks up any keys the player touches
+pub struct KeyPickupSystem;
+
+impl<'a> System<'a> for KeyPickupSystem {
+    type SystemData = (
+        FetchMut<'a, Keys>,
+        FetchMut<'a, Inventory>,
+        Fetch<'a, GlobalPlayerState>,
+    );
+
+    fn run(&mut self, (mut keys, mut inventory, player_state): Self::SystemData) {
+        for key in keys.0.iter_mut() {
+            if !key.collected && key.info.bounds.intersects(&player_state.bounds) {
+                key.collected = true;
+                inventory.keys.push(key.info.key.clone());
+            }
+        }
+    }
+}
+
+/// Keeps track of the time spent on the level, and finishes it once the player reaches an exit
+/// with the painting swapped
+pub struct LevelProgressSystem;
+
+impl<'a> System<'a> for LevelProgressSystem {
+    type SystemData = (
+        FetchMut<'a, LevelProgress>,
+        Fetch<'a, GlobalPlayerState>,
+        Fetch<'a, Exits>,
+        Fetch<'a, Paintings>,
+        Fetch<'a, DeltaTime<f32>>,
+    );
+
+    fn run(&mut self, (mut progress, player_state, exits, paintings, delta_time): Self::SystemData) {
+        if progress.complete || player_state.captured {
+            return;
+        }
+
+        progress.time += delta_time.delta_seconds;
+
+        // Levels without any paintings are finished by painting a replica at the easel instead
+        if !paintings.0.is_empty() {
+            progress.painting_swapped = paintings.all_swapped();
+            progress.accuracy = if progress.painting_swapped {
+                let total: f32 = paintings.0.iter().filter_map(|painting| painting.accuracy).sum();
+                Some(total / paintings.0.len() as f32)
+            } else {
+                None
+            };
+        }
+
+        if progress.painting_swapped
+            && exits.0.iter().any(|exit| exit.intersects(&player_state.bounds))
+        {
+            progress.complete = true;
+        }
+    }
+}
+
+/// A guard telling the guards around it where it has spotted the player
+pub struct RadioCall {
+    /// The guard making the call, which doesn't need telling
+    pub from: Entity,
+    /// Where the guard making the call is, since only guards in range hear it
+    pub origin: Point2<f32>,
+    /// Where the player was spotted
+    pub sighting: Point2<f32>,
+}
+
+pub struct GuardAiSystem {
+    pub noise_reader: ReaderId<NoiseEvent>,
+    /// The calls made last frame, which the other guards answer this frame
+    pub radio_calls: Vec<RadioCall>,
+}
+
+impl GuardAiSystem {
+    pub fn new(noise_reader: ReaderId<NoiseEvent>) -> Self {
+        GuardAiSystem {
+            noise_reader,
+            radio_calls: Vec::new(),
+        }
+    }
+}
 
 impl<'a> System<'a> for GuardAiSystem {
     #![allow(clippy)]
@@ -236,6 +1221,14 @@ impl<'a> System<'a> for GuardAiSystem {
         Fetch<'a, GuardTurnAroundBoxes>,
         Fetch<'a, DynamicBoundingVolumeTree2<f32>>,
         Fetch<'a, DeltaTime<f32>>,
+        FetchMut<'a, LevelProgress>,
+        Fetch<'a, Paintings>,
+        FetchMut<'a, Alarm>,
+        FetchMut<'a, Doors>,
+        Fetch<'a, HidingSpots>,
+        Fetch<'a, Shadows>,
+        Fetch<'a, NavGraph>,
+        FetchMut<'a, EventChannel<NoiseEvent>>,
     );
 
     fn run(
@@ -251,132 +1244,442 @@ impl<'a> System<'a> for GuardAiSystem {
             turn_around_boxes,
             tree,
             delta_time,
+            mut progress,
+            paintings,
+            mut alarm,
+            mut doors,
+            hiding_spots,
+            shadows,
+            nav,
+            mut noise_channel,
         ): Self::SystemData,
     ) {
-        //        use entity::components::GuardAi::*;
-        for (entity, mut collision_state, mut ai, mut directional, mut pose, mut shape, mut forces) in
-            (
-                &*entities,
-                &mut collision_state,
-                &mut ai,
-                &mut directional,
-                &mut rigid_body_parts.poses,
-                &mut rigid_body_parts.shapes,
-                &mut rigid_body_parts.forces,
-            ).join()
+        let noises: Vec<NoiseEvent> = noise_channel.read(&mut self.noise_reader).cloned().collect();
+        let calls = std::mem::replace(&mut self.radio_calls, Vec::new());
+        let mut barks = Vec::new();
+        let alert = alarm.is_raised();
+
+        // Guards and the player don't block sound like walls do
+        let bodies: Vec<u32> = (&*entities, &collision_state)
+            .join()
+            .map(|(entity, _collision_state)| entity.id())
+            .collect();
+
+        for (entity, collision_state, ai, directional, pose, shape, mut forces) in (
+            &*entities,
+            &mut collision_state,
+            &mut ai,
+            &mut directional,
+            &rigid_body_parts.poses,
+            &rigid_body_parts.shapes,
+            &mut rigid_body_parts.forces,
+        ).join()
             {
                 let position = pose.position();
 
-                let ray_dir = player_state.pos - position;
+                let cone = VisionCone::for_guard(ai, position, directional.direction);
+                let perception = {
+                    let senses = Senses {
+                        tree: &*tree,
+                        player: &*player_state,
+                        shadows: &*shadows,
+                        noises: &noises,
+                        bodies: &bodies,
+                        alert,
+                        delta_seconds: delta_time.delta_seconds,
+                    };
+                    perceive(ai, entity, &cone, &senses)
+                };
 
-                let mut found_player = false;
+                if ai.turn_around_cooldown > 0.0 {
+                    ai.turn_around_cooldown = (ai.turn_around_cooldown - delta_time.delta_seconds).max(0.0);
+                }
 
-                if ray_dir.x * directional.multiplier() > 0.0 || ai.state != GuardAiState::Patrolling {
-                    let ray = Ray2::new(
-                        Point2::new(position.x, position.y - ::GUARD_SIZE.1 * 0.25),
-                        ray_dir,
-                    );
-                    //            let angle = ray_dir.angle().0; // FIXME: make sure it actually check if in bounds of vision (60deg)
-
-                    use ord_subset::OrdSubsetIterExt;
-                    let collision = query_ray(&*tree, ray)
-                        .into_iter()
-                        .filter(|&(hit, _point)| hit.value.id() != entity.id())
-                        .ord_subset_min_by_key(|&(_hit, point)| (point - ray.origin).dot(ray.direction));
-                    match collision {
-                        None => (),
-                        Some((hit, _point)) => found_player = hit.value.id() == player_state.id,
+                let mut guard = Guard {
+                    ai,
+                    directional,
+                    collision_state,
+                    position,
+                    bound: *shape.bound(),
+                    found_player: perception.sighting.is_some(),
+                    walk_force: 0.0,
+                    jumped: false,
+                    barked: false,
+                };
+
+                // Every guard answers a new alarm report by heading over to search where the
+                // player was reported, and nearby guards answer each other's radio calls
+                if guard.ai.alarm_report != alarm.report {
+                    guard.ai.alarm_report = alarm.report;
+                    guard_ai::converge(&mut guard, alarm.last_known);
+                }
+                for call in calls.iter() {
+                    if call.from != entity && (call.origin - position).magnitude() <= RADIO_RANGE {
+                        guard_ai::converge(&mut guard, Some(call.sighting));
                     }
                 }
 
-                if ai.turn_around_cooldown > 0.0 {
-                    ai.turn_around_cooldown = (ai.turn_around_cooldown - delta_time.delta_seconds).max(0.0);
+                if let Some((source, volume)) = perception.heard {
+                    guard_ai::hear(&mut guard, source, volume);
                 }
 
-                let player_direction = if player_state.pos.x > pose.position().x {
-                    ::entity::components::Direction::Right
-                } else {
-                    ::entity::components::Direction::Left
+                let next_state = {
+                    let mut surroundings = Surroundings {
+                        player: &mut *player_state,
+                        jump_boxes: &*jump_boxes,
+                        turn_around_boxes: &*turn_around_boxes,
+                        paintings: &*paintings,
+                        hiding_spots: &*hiding_spots,
+                        nav: &*nav,
+                        alarm: &mut *alarm,
+                        delta_seconds: delta_time.delta_seconds,
+                    };
+                    guard_ai::update(&mut guard, &mut surroundings)
                 };
 
-                let bound = shape.bound();
+                if next_state != guard.ai.state {
+                    if next_state == GuardAiState::Chasing {
+                        progress.times_spotted += 1;
 
-                ai.state = match ai.state {
-                    GuardAiState::Searching => {
-                        if found_player {
-                            GuardAiState::Chasing
-                        } else {
-                            if collision_state.ground {
-                                for (direction_box, aabb) in
-                                    jump_boxes.0.iter().map(|b| (b.get_direction(), b.get_aabb2()))
-                                    {
-                                        if bound.intersects(aabb) {
-                                            if player_direction == direction_box && collision_state.jump_cooldown <= std::f32::EPSILON {
-                                                forces.add_force(Vector2::new(0.0, -1000.0));
-                                                collision_state.jump_cooldown = 0.25;
-                                            }
-                                        }
-                                    }
-                            }
-
-                            GuardAiState::Searching
-                        }
-                    }
-                    GuardAiState::Chasing => {
-                        if !found_player {
-                            GuardAiState::Searching
-                        } else {
-                            if bound.intersects(&player_state.bounds) {
-                                player_state.captured = true;
-                            }
-
-                            let player_above = player_state.pos.y < pose.position().y;
-
-                            let walk_speed = if collision_state.ground { 25.0 } else { 10.0 };
-                            forces.add_force(Vector2::new(player_direction.multiplier() * walk_speed, 0.0));
-
-                            if collision_state.ground {
-                                for (direction_box, aabb) in
-                                    jump_boxes.0.iter().map(|b| (b.get_direction(), b.get_aabb2()))
-                                    {
-                                        if bound.intersects(aabb) {
-                                            // TODO embed at map level
-                                            // FIXME for good measure
-                                            // TODO check if should jump always - MUST DO THIS ACTUALLY PROPERLY
-                                            if player_direction == direction_box && player_above && collision_state.jump_cooldown <= std::f32::EPSILON {
-                                                forces.add_force(Vector2::new(0.0, -1000.0));
-                                                collision_state.jump_cooldown = 0.25;
-                                            }
-                                        }
-                                    }
-                            }
-
-                            GuardAiState::Chasing
+                        // Dogs can't use radios, so they bark instead
+                        if guard.ai.info.kind != GuardKind::Dog {
+                            self.radio_calls.push(RadioCall {
+                                from: entity,
+                                origin: position,
+                                sighting: player_state.pos,
+                            });
                         }
                     }
-                    GuardAiState::Patrolling => {
-                        if found_player {
-                            GuardAiState::Chasing
-                        } else {
-                            let walk_speed = if collision_state.ground { 7.0 } else { 5.0 };
-                            forces.add_force(Vector2::new(
-                                directional.direction.multiplier() * walk_speed,
-                                0.0,
-                            ));
 
-                            let bound = shape.bound();
+                    guard_ai::enter(&mut guard, next_state);
+                    guard.ai.state = next_state;
+                }
 
-                            for aabb in turn_around_boxes.0.iter() {
-                                if bound.intersects(aabb) && ai.turn_around_cooldown <= std::f32::EPSILON {
-                                    directional.direction = directional.direction.invert();
-                                    ai.turn_around_cooldown = 0.75;
-                                }
-                            }
+                guard.ai.saw_player = guard.found_player;
 
-                            GuardAiState::Patrolling
-                        }
-                    }
+                // Guards open any unlocked doors in their way, but dogs can't
+                if guard.ai.info.kind != GuardKind::Dog {
+                    doors.open_near(&guard.bound, GUARD_DOOR_REACH);
+                }
+
+                if guard.barked {
+                    barks.push(NoiseEvent {
+                        position,
+                        loudness: BARK_LOUDNESS,
+                    });
+                }
+
+                forces.add_force(Vector2::new(guard.walk_force, 0.0));
+                if guard.jumped {
+                    forces.add_force(Vector2::new(0.0, -::JUMP_FORCE));
                 }
             }
+
+        for bark in barks {
+            noise_channel.single_write(bark);
+        }
+    }
+}
+
+/// Sweeps the security cameras around, raising the alarm when one of them spots the player.
+/// Cameras share their perception with guards, but have no body to move around with.
+pub struct SecurityCameraSystem;
+
+impl<'a> System<'a> for SecurityCameraSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, GuardAi>,
+        ReadStorage<'a, Directional>,
+        ReadStorage<'a, BodyPose2<f32>>,
+        Fetch<'a, GlobalPlayerState>,
+        Fetch<'a, DynamicBoundingVolumeTree2<f32>>,
+        Fetch<'a, Shadows>,
+        Fetch<'a, DeltaTime<f32>>,
+        FetchMut<'a, Alarm>,
+        FetchMut<'a, LevelProgress>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut ai,
+            directional,
+            poses,
+            player_state,
+            tree,
+            shadows,
+            delta_time,
+            mut alarm,
+            mut progress,
+        ): Self::SystemData,
+    ) {
+        let senses = Senses {
+            tree: &*tree,
+            player: &*player_state,
+            shadows: &*shadows,
+            noises: &[],
+            bodies: &[],
+            alert: alarm.is_raised(),
+            delta_seconds: delta_time.delta_seconds,
+        };
+
+        for (entity, ai, directional, pose) in (&*entities, &mut ai, &directional, &poses).join() {
+            if ai.info.kind != GuardKind::Camera {
+                continue;
+            }
+
+            let cone = VisionCone::for_guard(ai, pose.position(), directional.direction);
+            let found_player = perceive(ai, entity, &cone, &senses).sighting.is_some();
+
+            let next_state = guard_ai::watch(ai, found_player, &mut *alarm, delta_time.delta_seconds);
+            if next_state != ai.state && next_state == GuardAiState::Chasing {
+                progress.times_spotted += 1;
+            }
+            ai.state = next_state;
+            ai.saw_player = found_player;
+        }
+    }
+}
+
+/// Raises the alarm when the player walks into a tripwire. A tripwire goes off again once the
+/// player has left it and walked back in.
+pub struct TripwireSystem;
+
+impl<'a> System<'a> for TripwireSystem {
+    type SystemData = (
+        FetchMut<'a, Tripwires>,
+        FetchMut<'a, Alarm>,
+        Fetch<'a, GlobalPlayerState>,
+    );
+
+    fn run(&mut self, (mut tripwires, mut alarm, player_state): Self::SystemData) {
+        for tripwire in tripwires.0.iter_mut() {
+            let touching = tripwire.bounds.intersects(&player_state.bounds);
+            if touching && !tripwire.tripped {
+                alarm.escalate(Some(player_state.pos));
+            }
+            tripwire.tripped = touching;
+        }
+    }
+}
+
+/// Calms the alarm down once nothing has been reported for a while, and seals the lockdown doors
+/// while the level is in lockdown. Doors aren't sealed while the player is standing in them.
+pub struct AlarmSystem;
+
+impl<'a> System<'a> for AlarmSystem {
+    type SystemData = (
+        FetchMut<'a, Alarm>,
+        FetchMut<'a, Doors>,
+        Fetch<'a, GlobalPlayerState>,
+        Fetch<'a, DeltaTime<f32>>,
+    );
+
+    fn run(&mut self, (mut alarm, mut doors, player_state, delta_time): Self::SystemData) {
+        alarm.update(delta_time.delta_seconds);
+
+        let lockdown = alarm.level == AlarmLevel::Lockdown;
+        for door in doors.0.iter_mut().filter(|door| door.info.lockdown) {
+            if lockdown && !door.sealed && door.state != DoorState::Locked
+                && !door.info.bounds.intersects(&player_state.bounds)
+            {
+                door.state = DoorState::Locked;
+                door.sealed = true;
+            } else if !lockdown && door.sealed {
+                door.state = DoorState::Closed;
+                door.sealed = false;
+            }
+        }
+    }
+}
+
+/// Draws a border around the screen while the alarm is raised, which turns red in lockdown
+pub struct AlarmRenderSystem<'a> {
+    ctx: &'a mut ggez::Context,
+}
+
+impl<'a> AlarmRenderSystem<'a> {
+    pub fn new(ctx: &'a mut ggez::Context) -> Self {
+        AlarmRenderSystem { ctx }
+    }
+}
+
+impl<'a> System<'a> for AlarmRenderSystem<'a> {
+    type SystemData = (Fetch<'a, Alarm>, Fetch<'a, Camera>);
+
+    fn run(&mut self, (alarm, camera): Self::SystemData) {
+        let color = match alarm.level {
+            AlarmLevel::Calm => return,
+            AlarmLevel::Alert => Color::new(1.0, 0.5, 0.0, 0.6),
+            AlarmLevel::Lockdown => Color::new(1.0, 0.0, 0.0, 0.7),
+        };
+
+        let width = camera.view_size.x * 16.0;
+        let height = camera.view_size.y * 16.0;
+        let border = ALARM_BORDER_WIDTH;
+
+        graphics::set_color(self.ctx, color).expect("Error setting color!");
+        for rect in &[
+            graphics::Rect::new(0.0, 0.0, width, border),
+            graphics::Rect::new(0.0, height - border, width, border),
+            graphics::Rect::new(0.0, border, border, height - border * 2.0),
+            graphics::Rect::new(width - border, border, border, height - border * 2.0),
+        ] {
+            graphics::rectangle(self.ctx, DrawMode::Fill, *rect).expect("Error drawing alarm!");
+        }
+
+        graphics::set_color(self.ctx, Color::new(1.0, 1.0, 1.0, 1.0))
+            .expect("Error setting color!");
+    }
+}
+
+/// Moves the player's aim up and down while they're aiming a throw, and makes a noise where each
+/// thrown distraction first lands. Landed distractions are cleaned up after a while.
+pub struct DistractionSystem;
+
+impl<'a> System<'a> for DistractionSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Distraction>,
+        ReadStorage<'a, CollisionState>,
+        ReadStorage<'a, BodyPose2<f32>>,
+        Fetch<'a, GameInput>,
+        FetchMut<'a, Aim>,
+        Fetch<'a, DeltaTime<f32>>,
+        FetchMut<'a, EventChannel<NoiseEvent>>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut distractions,
+            collision_state,
+            poses,
+            input,
+            mut aim,
+            delta_time,
+            mut noises,
+        ): Self::SystemData,
+    ) {
+        if input.aiming {
+            let angle = aim.angle - input.move_vertical * AIM_SPEED * delta_time.delta_seconds;
+            aim.angle = angle.max(MIN_AIM_ANGLE).min(MAX_AIM_ANGLE);
+        }
+
+        for (entity, distraction, collision_state, pose) in
+            (&*entities, &mut distractions, &collision_state, &poses).join()
+        {
+            if !distraction.landed {
+                if collision_state.ground || collision_state.wall.is_some() {
+                    distraction.landed = true;
+                    distraction.linger_timer = DISTRACTION_LINGER_TIME;
+                    noises.single_write(NoiseEvent {
+                        position: pose.position(),
+                        loudness: DISTRACTION_LOUDNESS,
+                    });
+                }
+            } else {
+                distraction.linger_timer -= delta_time.delta_seconds;
+                if distraction.linger_timer <= 0.0 {
+                    entities
+                        .delete(entity)
+                        .expect("Failed to delete distraction!");
+                }
+            }
+        }
+    }
+}
+
+/// Draws the thrown distractions, how many the player has left in the corner of the screen, and
+/// the arc a throw would take while the player is aiming one
+pub struct DistractionRenderSystem<'a> {
+    ctx: &'a mut ggez::Context,
+}
+
+impl<'a> DistractionRenderSystem<'a> {
+    pub fn new(ctx: &'a mut ggez::Context) -> Self {
+        DistractionRenderSystem { ctx }
+    }
+}
+
+impl<'a> System<'a> for DistractionRenderSystem<'a> {
+    type SystemData = (
+        ReadStorage<'a, Distraction>,
+        ReadStorage<'a, BodyPose2<f32>>,
+        Fetch<'a, GameInput>,
+        Fetch<'a, Aim>,
+        Fetch<'a, Inventory>,
+        Fetch<'a, GlobalPlayerState>,
+        Fetch<'a, DynamicBoundingVolumeTree2<f32>>,
+        Fetch<'a, OneWayPlatforms>,
+        Fetch<'a, Camera>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            distractions,
+            poses,
+            input,
+            aim,
+            inventory,
+            player_state,
+            tree,
+            platforms,
+            camera,
+        ): Self::SystemData,
+    ) {
+        graphics::set_color(self.ctx, Color::new(0.95, 0.8, 0.3, 1.0))
+            .expect("Error setting color!");
+        for (_distraction, pose) in (&distractions, &poses).join() {
+            let position = pose.position();
+            let min = camera.to_screen(Point2::new(
+                position.x - DISTRACTION_SIZE / 2.0,
+                position.y - DISTRACTION_SIZE / 2.0,
+            ));
+            graphics::rectangle(
+                self.ctx,
+                DrawMode::Fill,
+                graphics::Rect::new(
+                    min.x,
+                    min.y,
+                    DISTRACTION_SIZE * 16.0,
+                    DISTRACTION_SIZE * 16.0,
+                ),
+            ).expect("Error drawing distraction!");
+        }
+
+        for i in 0..inventory.distractions {
+            graphics::rectangle(
+                self.ctx,
+                DrawMode::Fill,
+                graphics::Rect::new(
+                    DISTRACTION_COUNTER_MARGIN + i as f32 * DISTRACTION_COUNTER_SPACING,
+                    DISTRACTION_COUNTER_MARGIN,
+                    DISTRACTION_SIZE * 16.0,
+                    DISTRACTION_SIZE * 16.0,
+                ),
+            ).expect("Error drawing distraction counter!");
+        }
+
+        if input.aiming && inventory.distractions > 0 && player_state.hiding.is_none() {
+            let (origin, velocity) = distraction::launch(&*player_state, aim.angle);
+            let points: Vec<graphics::Point2> =
+                distraction::trajectory(&*tree, &*platforms, player_state.id, origin, velocity)
+                    .into_iter()
+                    .map(|point| camera.to_screen(point))
+                    .collect();
+
+            if points.len() >= 2 {
+                graphics::set_color(self.ctx, Color::new(1.0, 1.0, 1.0, 0.6))
+                    .expect("Error setting color!");
+                graphics::line(self.ctx, &points, 1.0).expect("Error drawing aim arc!");
+            }
+        }
+
+        graphics::set_color(self.ctx, Color::new(1.0, 1.0, 1.0, 1.0))
+            .expect("Error setting color!");
     }
 }
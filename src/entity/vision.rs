@@ -0,0 +1,192 @@
+use cgmath::{InnerSpace, Point2, Vector2};
+use collision::{dbvt::query_ray, Aabb2, Ray2};
+use ggez;
+use ggez::graphics::{self, DrawMode};
+use ord_subset::OrdSubsetIterExt;
+use rhusics_ecs::physics2d::DynamicBoundingVolumeTree2;
+use specs::Entity;
+use camera::Camera;
+use level::GuardKind;
+use super::components::{Direction, GuardAi};
+
+/// How far inside the corners of a body the points a guard looks for are, in tiles
+const SAMPLE_INSET: f32 = 0.1;
+/// How many straight segments the arcs of a cone are drawn with
+const ARC_SEGMENTS: usize = 12;
+
+/// How well a guard can see something, from worst to best
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum VisionZone {
+    /// Out of the corner of the guard's eye, which it is slower to react to
+    Peripheral,
+    /// Inside of the main cone in front of the guard
+    Focused,
+    /// Close enough that the guard notices it whichever way it is facing
+    Near,
+}
+
+/// What a guard can see of a body
+#[derive(Debug, Copy, Clone)]
+pub struct Sighting {
+    /// The best zone any visible part of the body is in
+    pub zone: VisionZone,
+    /// How much of the body is visible, from 0 to 1
+    pub exposure: f32,
+}
+
+/// The area a guard can see, in tiles
+#[derive(Debug, Copy, Clone)]
+pub struct VisionCone {
+    /// Where the guard's eyes are
+    pub origin: Point2<f32>,
+    /// The direction the guard is looking in, as a unit vector
+    pub facing: Vector2<f32>,
+    /// Half the width of the main cone, in radians
+    pub half_angle: f32,
+    /// How far the main cone reaches
+    pub range: f32,
+    /// Half the width of the wider, shorter cone of peripheral vision, in radians
+    pub peripheral_half_angle: f32,
+    /// How far peripheral vision reaches
+    pub peripheral_range: f32,
+    /// How close something has to be to be seen from any direction
+    pub near_range: f32,
+}
+
+impl VisionCone {
+    /// The cone of a guard at `position` and facing in `direction`
+    pub fn for_guard(ai: &GuardAi, position: Point2<f32>, direction: Direction) -> Self {
+        let info = &ai.info;
+
+        // Cameras see from where they're mounted, and guards from their eyes
+        let origin = match info.kind {
+            GuardKind::Camera => position,
+            _ => Point2::new(position.x, position.y - ::GUARD_SIZE.1 * 0.25),
+        };
+
+        VisionCone {
+            origin,
+            facing: Vector2::new(
+                direction.multiplier() * ai.look_angle.cos(),
+                ai.look_angle.sin(),
+            ),
+            half_angle: info.vision_angle / 2.0,
+            range: info.vision_range,
+            peripheral_half_angle: info.peripheral_angle / 2.0,
+            peripheral_range: info.peripheral_range,
+            near_range: info.near_range,
+        }
+    }
+
+    /// Which part of the cone a point is in, if any, without checking whether anything is in
+    /// the way
+    pub fn zone(&self, point: Point2<f32>) -> Option<VisionZone> {
+        let offset = point - self.origin;
+        let distance = offset.magnitude();
+
+        if distance <= self.near_range {
+            return Some(VisionZone::Near);
+        }
+
+        let angle = self.facing.angle(offset).0.abs();
+        if distance <= self.range && angle <= self.half_angle {
+            Some(VisionZone::Focused)
+        } else if distance <= self.peripheral_range && angle <= self.peripheral_half_angle {
+            Some(VisionZone::Peripheral)
+        } else {
+            None
+        }
+    }
+
+    /// How well the guard `viewer` can see the body with the given id and bounds, if at all.
+    ///
+    /// Rays are cast to the centre and near each corner of the bounds, so a body which is only
+    /// partly behind cover can still be seen, but is less exposed.
+    pub fn can_see(
+        &self,
+        tree: &DynamicBoundingVolumeTree2<f32>,
+        viewer: Entity,
+        target: u32,
+        bounds: &Aabb2<f32>,
+    ) -> Option<Sighting> {
+        let centre = bounds.min + (bounds.max - bounds.min) / 2.0;
+        let samples = [
+            centre,
+            Point2::new(bounds.min.x + SAMPLE_INSET, bounds.min.y + SAMPLE_INSET),
+            Point2::new(bounds.max.x - SAMPLE_INSET, bounds.min.y + SAMPLE_INSET),
+            Point2::new(bounds.min.x + SAMPLE_INSET, bounds.max.y - SAMPLE_INSET),
+            Point2::new(bounds.max.x - SAMPLE_INSET, bounds.max.y - SAMPLE_INSET),
+        ];
+
+        let visible: Vec<VisionZone> = samples
+            .iter()
+            .filter_map(|&point| self.zone(point).map(|zone| (point, zone)))
+            .filter(|&(point, _zone)| self.unobstructed(tree, viewer, target, point))
+            .map(|(_point, zone)| zone)
+            .collect();
+
+        visible.iter().cloned().max().map(|zone| Sighting {
+            zone,
+            exposure: visible.len() as f32 / samples.len() as f32,
+        })
+    }
+
+    /// Whether the first thing a ray from the eyes towards `point` hits is the target
+    fn unobstructed(
+        &self,
+        tree: &DynamicBoundingVolumeTree2<f32>,
+        viewer: Entity,
+        target: u32,
+        point: Point2<f32>,
+    ) -> bool {
+        let ray = Ray2::new(self.origin, point - self.origin);
+
+        let collision = query_ray(tree, ray)
+            .into_iter()
+            .filter(|&(hit, _point)| hit.value.id() != viewer.id())
+            .ord_subset_min_by_key(|&(_hit, point)| (point - ray.origin).dot(ray.direction));
+
+        match collision {
+            Some((hit, _point)) => hit.value.id() == target,
+            None => false,
+        }
+    }
+
+    /// Fills in the main cone, to show the light from a flashlight or camera
+    pub fn fill(&self, ctx: &mut ggez::Context, camera: &Camera) -> ggez::GameResult<()> {
+        graphics::polygon(
+            ctx,
+            DrawMode::Fill,
+            &self.arc(self.half_angle, self.range, camera),
+        )
+    }
+
+    /// Draws the outline of the cone, for the debug overlay
+    pub fn draw(&self, ctx: &mut ggez::Context, camera: &Camera) -> ggez::GameResult<()> {
+        let origin = camera.to_screen(self.origin);
+
+        graphics::polygon(
+            ctx,
+            DrawMode::Line(1.0),
+            &self.arc(self.half_angle, self.range, camera),
+        )?;
+        graphics::polygon(
+            ctx,
+            DrawMode::Line(1.0),
+            &self.arc(self.peripheral_half_angle, self.peripheral_range, camera),
+        )?;
+        graphics::circle(ctx, DrawMode::Line(1.0), origin, self.near_range * 16.0, 0.5)
+    }
+
+    /// The outline of a wedge of the cone on screen, starting and ending at the eyes
+    fn arc(&self, half_angle: f32, range: f32, camera: &Camera) -> Vec<graphics::Point2> {
+        let facing = self.facing.y.atan2(self.facing.x);
+
+        let mut points = vec![camera.to_screen(self.origin)];
+        points.extend((0..ARC_SEGMENTS + 1).map(|i| {
+            let angle = facing - half_angle + half_angle * 2.0 * i as f32 / ARC_SEGMENTS as f32;
+            camera.to_screen(self.origin + Vector2::new(angle.cos(), angle.sin()) * range)
+        }));
+        points
+    }
+}
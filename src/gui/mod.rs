@@ -1,8 +1,28 @@
 use ggez::{self, error::GameResult, graphics};
+use image::RgbaImage;
 use std::clone::Clone;
 use std::marker::PhantomData;
 
+/// Fonts are loaded at twice the size text is drawn at, so that text stays sharp when the screen
+/// is scaled up
+pub const TEXT_SCALE: f32 = 0.5;
+
+/// The gap left between lines drawn by `draw_centred_lines`
+const LINE_SPACING: f32 = 2.0;
+
+/// Something a gui wants the game to do, returned from `Gui::poll_outcome`
+pub enum GuiOutcome {
+    /// The replica painting was finished, with how closely it matches the original from 0 to 1
+    PaintingFinished { accuracy: f32, replica: RgbaImage },
+    /// Move on to the next level
+    NextLevel,
+}
+
 pub trait Gui {
+    /// Lays the gui out for a screen of the given logical size. This is called when the gui is
+    /// shown, and again whenever the window changes size.
+    fn resize(&mut self, width: f32, height: f32);
+
     fn update(&mut self, mouse_x: f32, mouse_y: f32) -> GameResult<()>;
 
     fn draw(&mut self, ctx: &mut ggez::Context, font: &graphics::Font, mouse_x: f32, mouse_y: f32) -> GameResult<()>;
@@ -10,6 +30,11 @@ pub trait Gui {
     fn mouse_pressed(&mut self, mouse_x: f32, mouse_y: f32);
 
     fn mouse_released(&mut self, mouse_x: f32, mouse_y: f32);
+
+    /// Takes whatever the gui wants the game to do, if anything. This is checked every update.
+    fn poll_outcome(&mut self) -> Option<GuiOutcome> {
+        None
+    }
 }
 
 pub trait ButtonType<S: GuiState>: Clone {
@@ -43,6 +68,14 @@ impl<S: GuiState, T: ButtonType<S>> GuiComponents<S, T> {
         Ok(())
     }
 
+    /// Moves every button by the given amount
+    pub fn translate(&mut self, x: f32, y: f32) {
+        for button in self.buttons.iter_mut() {
+            button.pos.x += x;
+            button.pos.y += y;
+        }
+    }
+
     pub fn mouse_pressed(&self, state: &mut S, mouse_x: f32, mouse_y: f32) {
         let button_types: Vec<Box<T>> = self.buttons
             .iter()
@@ -127,12 +160,7 @@ pub fn draw_rectangle(
     graphics::rectangle(
         ctx,
         graphics::DrawMode::Fill,
-        graphics::Rect::new(
-            pos.x * ::GLOBAL_SCALE,
-            pos.y * ::GLOBAL_SCALE,
-            size.x * ::GLOBAL_SCALE,
-            size.y * ::GLOBAL_SCALE,
-        ),
+        graphics::Rect::new(pos.x, pos.y, size.x, size.y),
     )?;
 
     graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
@@ -150,12 +178,70 @@ pub fn draw_texture(
         icon,
         graphics::DrawParam {
             src: graphics::Rect::one(),
-            dest: graphics::Point2::new(pos.x * ::GLOBAL_SCALE, pos.y * ::GLOBAL_SCALE),
+            dest: pos,
+            rotation: 0.0,
+            scale: graphics::Point2::new(1.0, 1.0),
+            offset: graphics::Point2::new(0.0, 0.0),
+            shear: graphics::Point2::new(0.0, 0.0),
+            color: None,
+        },
+    )
+}
+
+/// Draws text at the given position, scaled down by `TEXT_SCALE`
+pub fn draw_text(
+    ctx: &mut ggez::Context,
+    text: &graphics::Text,
+    pos: graphics::Point2,
+) -> GameResult<()> {
+    graphics::draw_ex(
+        ctx,
+        text,
+        graphics::DrawParam {
+            src: graphics::Rect::one(),
+            dest: pos,
             rotation: 0.0,
-            scale: graphics::Point2::new(::GLOBAL_SCALE, ::GLOBAL_SCALE),
+            scale: graphics::Point2::new(TEXT_SCALE, TEXT_SCALE),
             offset: graphics::Point2::new(0.0, 0.0),
             shear: graphics::Point2::new(0.0, 0.0),
             color: None,
         },
     )
 }
+
+/// Darkens a screen of the given size and draws the given lines of text over it, centred in the
+/// middle
+pub fn draw_centred_lines(
+    ctx: &mut ggez::Context,
+    font: &graphics::Font,
+    size: (f32, f32),
+    lines: &[String],
+) -> GameResult<()> {
+    draw_rectangle(
+        ctx,
+        graphics::Point2::new(0.0, 0.0),
+        graphics::Point2::new(size.0, size.1),
+        graphics::Color::new(0.1, 0.1, 0.1, 0.8),
+    )?;
+
+    // Empty lines still need to take up space, so give them a space to render
+    let mut texts = Vec::with_capacity(lines.len());
+    for line in lines {
+        let line = if line.is_empty() { " " } else { line.as_str() };
+        texts.push(graphics::Text::new(ctx, line, font)?);
+    }
+
+    let total_height = texts
+        .iter()
+        .map(|text| text.height() as f32 * TEXT_SCALE + LINE_SPACING)
+        .sum::<f32>();
+    let mut y = (size.1 - total_height) / 2.0;
+
+    for text in &texts {
+        let width = text.width() as f32 * TEXT_SCALE;
+        draw_text(ctx, text, graphics::Point2::new((size.0 - width) / 2.0, y))?;
+        y += text.height() as f32 * TEXT_SCALE + LINE_SPACING;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use ggez::GameError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use tiled::TiledError;
+
+/// Something which stopped a level from loading. Every variant carries the map file it came from.
+#[derive(Debug)]
+pub enum LevelError {
+    /// The map file couldn't be read
+    Io { file: PathBuf, error: io::Error },
+    /// The map file isn't a valid tiled map
+    Parse { file: PathBuf, error: TiledError },
+    /// An object the level needs is not in the `objects` layer
+    MissingObject { file: PathBuf, name: &'static str },
+    /// An object has a shape that can't be used for what it is
+    BadShape {
+        file: PathBuf,
+        object: u32,
+        name: String,
+        expected: &'static str,
+    },
+    /// An object is missing a custom property it needs
+    MissingProperty {
+        file: PathBuf,
+        object: u32,
+        name: String,
+        property: &'static str,
+    },
+    /// An image used by the level couldn't be loaded
+    Asset {
+        file: PathBuf,
+        path: String,
+        error: String,
+    },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LevelError::Io { ref file, ref error } => {
+                write!(f, "{}: could not read map: {}", file.display(), error)
+            }
+            LevelError::Parse { ref file, ref error } => {
+                write!(f, "{}: could not parse map: {:?}", file.display(), error)
+            }
+            LevelError::MissingObject { ref file, name } => write!(
+                f,
+                "{}: missing an object named `{}` in the `objects` layer",
+                file.display(),
+                name
+            ),
+            LevelError::BadShape {
+                ref file,
+                object,
+                ref name,
+                expected,
+            } => write!(
+                f,
+                "{}: object {} (`{}`) should be a {}",
+                file.display(),
+                object,
+                name,
+                expected
+            ),
+            LevelError::MissingProperty {
+                ref file,
+                object,
+                ref name,
+                property,
+            } => write!(
+                f,
+                "{}: object {} (`{}`) needs a `{}` property",
+                file.display(),
+                object,
+                name,
+                property
+            ),
+            LevelError::Asset {
+                ref file,
+                ref path,
+                ref error,
+            } => write!(
+                f,
+                "{}: could not load `{}`: {}",
+                file.display(),
+                path,
+                error
+            ),
+        }
+    }
+}
+
+impl Error for LevelError {
+    fn description(&self) -> &str {
+        match *self {
+            LevelError::Io { .. } => "could not read map",
+            LevelError::Parse { .. } => "could not parse map",
+            LevelError::MissingObject { .. } => "missing object",
+            LevelError::BadShape { .. } => "object has the wrong shape",
+            LevelError::MissingProperty { .. } => "object is missing a property",
+            LevelError::Asset { .. } => "could not load asset",
+        }
+    }
+}
+
+impl From<LevelError> for GameError {
+    fn from(error: LevelError) -> GameError {
+        GameError::ResourceLoadError(error.to_string())
+    }
+}
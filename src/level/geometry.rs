@@ -0,0 +1,483 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point2, Vector2};
+use collision::Aabb2;
+use collision::primitive::{ConvexPolygon, Primitive2, Rectangle};
+
+/// How thick the collision boxes generated for polyline edges are, in tiles
+const EDGE_THICKNESS: f32 = 0.125;
+/// How many sides the polygons generated for ellipses have
+const ELLIPSE_SEGMENTS: usize = 16;
+/// How far off a straight line a polygon vertex can be before it counts as a corner
+const COLLINEAR_TOLERANCE: f32 = 1e-4;
+
+/// A piece of static level geometry, in tile units
+#[derive(Debug, Clone)]
+pub enum CollisionGeometry {
+    /// An axis aligned box
+    Rect(Aabb2<f32>),
+    /// A convex polygon with its vertices in counter-clockwise order
+    Convex(Vec<Point2<f32>>),
+    /// A single segment of a polyline
+    Edge(Point2<f32>, Point2<f32>),
+}
+
+impl CollisionGeometry {
+    /// Creates the geometry for a polygon, splitting it up into convex pieces if it is concave.
+    /// Returns `None` if the polygon isn't simple, and so can't be split up.
+    pub fn polygon(points: &[Point2<f32>]) -> Option<Vec<CollisionGeometry>> {
+        decompose(points).map(|pieces| {
+            pieces
+                .into_iter()
+                .map(CollisionGeometry::Convex)
+                .collect()
+        })
+    }
+
+    /// Creates an edge chain following the given points
+    pub fn polyline(points: &[Point2<f32>]) -> Vec<CollisionGeometry> {
+        points
+            .windows(2)
+            .filter(|pair| pair[0] != pair[1])
+            .map(|pair| CollisionGeometry::Edge(pair[0], pair[1]))
+            .collect()
+    }
+
+    /// Approximates an ellipse within the given bounds with a convex polygon
+    pub fn ellipse(bounds: Aabb2<f32>) -> CollisionGeometry {
+        use std::f32::consts::PI;
+
+        let centre = bounds.min + (bounds.max - bounds.min) / 2.0;
+        let radii = (bounds.max - bounds.min) / 2.0;
+        let points = (0..ELLIPSE_SEGMENTS)
+            .map(|i| {
+                let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * 2.0 * PI;
+                Point2::new(
+                    centre.x + radii.x * angle.cos(),
+                    centre.y + radii.y * angle.sin(),
+                )
+            })
+            .collect();
+
+        CollisionGeometry::Convex(points)
+    }
+
+    /// The bounding box of this geometry
+    pub fn bound(&self) -> Aabb2<f32> {
+        match *self {
+            CollisionGeometry::Rect(aabb) => aabb,
+            CollisionGeometry::Convex(ref points) => bounding_box(points),
+            CollisionGeometry::Edge(start, end) => bounding_box(&[start, end]),
+        }
+    }
+
+    /// Builds the collision primitive for this geometry, returning it alongside the position it
+    /// should be placed at.
+    ///
+    /// Edges are turned into thin boxes rather than line primitives, since the contact generation
+    /// can't find a penetration depth against a shape with no area.
+    pub fn primitive(&self) -> (Primitive2<f32>, Point2<f32>) {
+        match *self {
+            CollisionGeometry::Rect(aabb) => {
+                let Vector2 { x: w, y: h } = aabb.max - aabb.min;
+                (
+                    Rectangle::new(w, h).into(),
+                    Point2::new(aabb.min.x + w / 2.0, aabb.min.y + h / 2.0),
+                )
+            }
+            CollisionGeometry::Convex(ref points) => convex_primitive(points),
+            CollisionGeometry::Edge(start, end) => convex_primitive(&edge_outline(start, end)),
+        }
+    }
+
+    /// The outline of this geometry as a convex polygon, matching the shape of its collision
+    /// primitive
+    pub fn outline(&self) -> Vec<Point2<f32>> {
+        match *self {
+            CollisionGeometry::Rect(aabb) => rect_outline(&aabb).to_vec(),
+            CollisionGeometry::Convex(ref points) => points.clone(),
+            CollisionGeometry::Edge(start, end) => edge_outline(start, end).to_vec(),
+        }
+    }
+
+    /// The range of x this geometry covers between two heights, or `None` if it doesn't reach in
+    /// between them at all
+    pub fn x_extent(&self, top: f32, bottom: f32) -> Option<(f32, f32)> {
+        let outline = self.outline();
+        let len = outline.len();
+        let bound = self.bound();
+        if bound.max.y <= top || bound.min.y >= bottom {
+            return None;
+        }
+
+        // The piece of a convex shape between two heights is made of its corners between them and
+        // the places its edges cross them
+        let mut xs: Vec<f32> = outline
+            .iter()
+            .filter(|point| top <= point.y && point.y <= bottom)
+            .map(|point| point.x)
+            .collect();
+        for i in 0..len {
+            let (a, b) = (outline[i], outline[(i + 1) % len]);
+            for &y in &[top, bottom] {
+                if (a.y - y) * (b.y - y) < 0.0 {
+                    xs.push(a.x + (b.x - a.x) * (y - a.y) / (b.y - a.y));
+                }
+            }
+        }
+
+        let min = xs.iter().cloned().fold(::std::f32::INFINITY, f32::min);
+        let max = xs.iter().cloned().fold(::std::f32::NEG_INFINITY, f32::max);
+        Some((min, max))
+    }
+
+    /// Whether the given point is inside this geometry or on its edge
+    pub fn contains(&self, point: Point2<f32>) -> bool {
+        let outline = self.outline();
+        let len = outline.len();
+        let sides: Vec<f32> = (0..len)
+            .map(|i| cross(outline[i], outline[(i + 1) % len], point))
+            .collect();
+
+        sides.iter().all(|&side| side >= 0.0) || sides.iter().all(|&side| side <= 0.0)
+    }
+
+    /// Whether this geometry overlaps the given box. Shapes which only touch along an edge don't
+    /// count as overlapping.
+    pub fn intersects(&self, aabb: &Aabb2<f32>) -> bool {
+        let outline = self.outline();
+        let corners = rect_outline(aabb);
+        let len = outline.len();
+
+        // The shapes are separate if there's a gap between them along the box's axes or along the
+        // normal of any of the outline's edges
+        let normals = (0..len).map(|i| {
+            let edge = outline[(i + 1) % len] - outline[i];
+            Vector2::new(-edge.y, edge.x)
+        });
+
+        vec![Vector2::new(1.0, 0.0), Vector2::new(0.0, 1.0)]
+            .into_iter()
+            .chain(normals)
+            .filter(|axis| axis.magnitude2() > 0.0)
+            .all(|axis| {
+                let (min, max) = project(&outline, axis);
+                let (aabb_min, aabb_max) = project(&corners, axis);
+                max > aabb_min && aabb_max > min
+            })
+    }
+}
+
+/// The corners of a box, in the same winding order as the convex pieces
+fn rect_outline(aabb: &Aabb2<f32>) -> [Point2<f32>; 4] {
+    [
+        aabb.min,
+        Point2::new(aabb.max.x, aabb.min.y),
+        aabb.max,
+        Point2::new(aabb.min.x, aabb.max.y),
+    ]
+}
+
+/// The thin box which stands in for a polyline edge
+fn edge_outline(start: Point2<f32>, end: Point2<f32>) -> [Point2<f32>; 4] {
+    let normal = {
+        let along = (end - start).normalize();
+        Vector2::new(-along.y, along.x) * (EDGE_THICKNESS / 2.0)
+    };
+
+    [start - normal, end - normal, end + normal, start + normal]
+}
+
+/// The range the points cover along an axis
+fn project(points: &[Point2<f32>], axis: Vector2<f32>) -> (f32, f32) {
+    points.iter().fold((::std::f32::INFINITY, ::std::f32::NEG_INFINITY), |(min, max), point| {
+        let distance = point.to_vec().dot(axis);
+        (min.min(distance), max.max(distance))
+    })
+}
+
+fn convex_primitive(points: &[Point2<f32>]) -> (Primitive2<f32>, Point2<f32>) {
+    let centre = centroid(points);
+    let local = points.iter().map(|&point| Point2::from_vec(point - centre)).collect();
+
+    (ConvexPolygon::new(local).into(), centre)
+}
+
+fn centroid(points: &[Point2<f32>]) -> Point2<f32> {
+    let sum = points
+        .iter()
+        .fold(Vector2::new(0.0, 0.0), |sum, point| sum + point.to_vec());
+    Point2::from_vec(sum / points.len() as f32)
+}
+
+fn bounding_box(points: &[Point2<f32>]) -> Aabb2<f32> {
+    let mut min = points[0];
+    let mut max = points[0];
+    for point in points {
+        min.x = min.x.min(point.x);
+        min.y = min.y.min(point.y);
+        max.x = max.x.max(point.x);
+        max.y = max.y.max(point.y);
+    }
+
+    Aabb2::new(min, max)
+}
+
+/// Twice the signed area of the polygon. Positive for counter-clockwise winding.
+fn signed_area(points: &[Point2<f32>]) -> f32 {
+    (0..points.len())
+        .map(|i| {
+            let a = points[i];
+            let b = points[(i + 1) % points.len()];
+            a.x * b.y - b.x * a.y
+        })
+        .sum()
+}
+
+fn cross(a: Point2<f32>, b: Point2<f32>, c: Point2<f32>) -> f32 {
+    let ab = b - a;
+    let bc = c - b;
+    ab.x * bc.y - ab.y * bc.x
+}
+
+fn is_convex(points: &[Point2<f32>]) -> bool {
+    let len = points.len();
+    (0..len).all(|i| cross(points[i], points[(i + 1) % len], points[(i + 2) % len]) >= -::std::f32::EPSILON)
+}
+
+/// Whether `point` is inside or on the edge of the counter-clockwise triangle `a`, `b`, `c`.
+/// Points sitting exactly on one of its corners don't count, since a polygon can touch itself
+/// there without getting in the way of the ear.
+fn in_triangle(point: Point2<f32>, a: Point2<f32>, b: Point2<f32>, c: Point2<f32>) -> bool {
+    point != a
+        && point != b
+        && point != c
+        && cross(a, b, point) >= 0.0
+        && cross(b, c, point) >= 0.0
+        && cross(c, a, point) >= 0.0
+}
+
+/// Removes any vertices which lie on the straight line between their neighbours
+fn remove_collinear(points: &mut Vec<Point2<f32>>) {
+    let mut i = 0;
+    while points.len() >= 3 && i < points.len() {
+        let len = points.len();
+        let prev = points[(i + len - 1) % len];
+        let next = points[(i + 1) % len];
+
+        if cross(prev, points[i], next).abs() <= COLLINEAR_TOLERANCE {
+            points.remove(i);
+            // The previous vertex might have become collinear now this one is gone
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Splits a simple polygon into convex pieces, each with counter-clockwise winding.
+///
+/// The polygon is first triangulated by ear clipping, and then neighbouring pieces are merged back
+/// together wherever the result is still convex (Hertel-Mehlhorn), to keep the number of bodies
+/// down. Returns `None` if the polygon has no area or crosses over itself.
+pub fn decompose(points: &[Point2<f32>]) -> Option<Vec<Vec<Point2<f32>>>> {
+    let mut points = points.to_vec();
+    points.dedup();
+    if points.len() > 1 && points[0] == points[points.len() - 1] {
+        points.pop();
+    }
+    remove_collinear(&mut points);
+
+    if points.len() < 3 || signed_area(&points).abs() <= ::std::f32::EPSILON {
+        return None;
+    }
+
+    if signed_area(&points) < 0.0 {
+        points.reverse();
+    }
+
+    if is_convex(&points) {
+        return Some(vec![points]);
+    }
+
+    let mut pieces = triangulate(&points)?;
+
+    // Merge pieces which share an edge for as long as it keeps them convex
+    let mut merged = true;
+    while merged {
+        merged = false;
+
+        'search: for i in 0..pieces.len() {
+            for j in i + 1..pieces.len() {
+                if let Some(piece) = merge(&pieces[i], &pieces[j]) {
+                    if is_convex(&piece.iter().map(|&index| points[index]).collect::<Vec<_>>()) {
+                        pieces[i] = piece;
+                        pieces.remove(j);
+                        merged = true;
+                        break 'search;
+                    }
+                }
+            }
+        }
+    }
+
+    Some(
+        pieces
+            .into_iter()
+            .map(|piece| piece.into_iter().map(|index| points[index]).collect())
+            .collect(),
+    )
+}
+
+/// Ear clips a counter-clockwise polygon into triangles of indices into `points`. Returns `None`
+/// if it runs out of ears, which means the polygon crosses over itself.
+fn triangulate(points: &[Point2<f32>]) -> Option<Vec<Vec<usize>>> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+
+    while remaining.len() > 3 {
+        let len = remaining.len();
+        let ear = (0..len).find(|&i| {
+            let prev = remaining[(i + len - 1) % len];
+            let current = remaining[i];
+            let next = remaining[(i + 1) % len];
+            let (a, b, c) = (points[prev], points[current], points[next]);
+
+            cross(a, b, c) > 0.0
+                && !remaining
+                    .iter()
+                    .filter(|&&other| other != prev && other != current && other != next)
+                    .any(|&other| in_triangle(points[other], a, b, c))
+        });
+
+        match ear {
+            Some(i) => {
+                triangles.push(vec![
+                    remaining[(i + len - 1) % len],
+                    remaining[i],
+                    remaining[(i + 1) % len],
+                ]);
+                remaining.remove(i);
+            }
+            None => return None,
+        }
+    }
+
+    triangles.push(remaining);
+    Some(triangles)
+}
+
+/// Joins two pieces along an edge they share, if they share one
+fn merge(first: &[usize], second: &[usize]) -> Option<Vec<usize>> {
+    let first_len = first.len();
+    let second_len = second.len();
+
+    for i in 0..first_len {
+        let a = first[i];
+        let b = first[(i + 1) % first_len];
+
+        // The shared edge runs in the opposite direction in the other piece
+        let shared = (0..second_len).find(|&j| second[j] == b && second[(j + 1) % second_len] == a);
+
+        if let Some(j) = shared {
+            // Walk the first piece from b around to a, then the second from after a to before b
+            let mut piece: Vec<usize> = (0..first_len)
+                .map(|offset| first[(i + 1 + offset) % first_len])
+                .collect();
+            piece.extend((2..second_len).map(|offset| second[(j + offset) % second_len]));
+            return Some(piece);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(coords: &[(f32, f32)]) -> Vec<Point2<f32>> {
+        coords.iter().map(|&(x, y)| Point2::new(x, y)).collect()
+    }
+
+    /// Checks every piece is convex and counter-clockwise, and that together they cover `area`
+    fn assert_pieces(pieces: &[Vec<Point2<f32>>], area: f32) {
+        for piece in pieces {
+            assert!(signed_area(piece) > 0.0, "piece is clockwise: {:?}", piece);
+            assert!(is_convex(piece), "piece is concave: {:?}", piece);
+        }
+
+        let total: f32 = pieces.iter().map(|piece| signed_area(piece) / 2.0).sum();
+        assert!((total - area).abs() < 1e-4, "pieces cover {} instead of {}", total, area);
+    }
+
+    #[test]
+    fn l_shape() {
+        let pieces = decompose(&points(&[
+            (0.0, 0.0),
+            (2.0, 0.0),
+            (2.0, 1.0),
+            (1.0, 1.0),
+            (1.0, 2.0),
+            (0.0, 2.0),
+        ])).unwrap();
+
+        assert_pieces(&pieces, 3.0);
+    }
+
+    #[test]
+    fn collinear_points() {
+        let square = decompose(&points(&[
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (2.0, 0.0),
+            (2.0, 2.0),
+            (0.0, 2.0),
+            (0.0, 1.0),
+        ])).unwrap();
+        assert_eq!(square, vec![points(&[(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)])]);
+
+        let l_shape = decompose(&points(&[
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (2.0, 0.0),
+            (2.0, 1.0),
+            (1.0, 1.0),
+            (1.0, 2.0),
+            (0.0, 2.0),
+            (0.0, 1.0),
+        ])).unwrap();
+        assert_pieces(&l_shape, 3.0);
+    }
+
+    #[test]
+    fn clockwise_input() {
+        let pieces = decompose(&points(&[
+            (0.0, 2.0),
+            (1.0, 2.0),
+            (1.0, 1.0),
+            (2.0, 1.0),
+            (2.0, 0.0),
+            (0.0, 0.0),
+        ])).unwrap();
+
+        assert_pieces(&pieces, 3.0);
+    }
+
+    #[test]
+    fn slope_overlap() {
+        // A slope rising to the right, with the space above it left open
+        let slope = CollisionGeometry::Convex(points(&[(0.0, 2.0), (2.0, 2.0), (2.0, 0.0)]));
+
+        assert!(slope.contains(Point2::new(1.5, 1.5)));
+        assert!(!slope.contains(Point2::new(0.5, 0.5)));
+
+        assert!(slope.intersects(&Aabb2::new(Point2::new(1.0, 1.0), Point2::new(1.5, 1.5))));
+        assert!(!slope.intersects(&Aabb2::new(Point2::new(0.0, 0.0), Point2::new(0.75, 0.75))));
+        // Resting on the bottom edge only touches it
+        assert!(!slope.intersects(&Aabb2::new(Point2::new(0.0, 2.0), Point2::new(2.0, 3.0))));
+    }
+
+    #[test]
+    fn self_intersecting() {
+        assert!(decompose(&points(&[(0.0, 0.0), (1.0, 1.0), (1.0, 0.0), (0.0, 1.0)])).is_none());
+    }
+}
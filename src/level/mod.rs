@@ -1,26 +1,374 @@
-use cgmath::Point2;
+use cgmath::{Point2, Vector2};
+use camera::Camera;
 use collision::Aabb2;
+use entity::components::Direction;
 use ggez::graphics::spritebatch::SpriteBatch;
 use ggez::graphics::{self, DrawParam, Drawable, Image, Rect};
-use ggez::{self, GameResult};
+use ggez;
 use std::fs::File;
 use std::io::BufReader;
-use tiled::{self, Map, Object};
+use std::path::{Path, PathBuf};
+use tiled::{self, Map, Object, PropertyValue};
 use util;
 
+pub use self::error::LevelError;
+pub use self::geometry::CollisionGeometry;
+pub use self::nav::{NavEdge, NavEdgeKind, NavGraph, NavNode};
+pub use self::watch::LevelWatcher;
+
+use self::tiles::TileGrid;
+
+pub mod validate;
+
+mod error;
+mod geometry;
+mod nav;
+mod tiles;
+mod watch;
+
 pub struct Level {
     tilesets: Vec<LoadedTileset>,
-    pub collision_rects: Vec<Aabb2<f32>>,
+    /// The map file the level was loaded from
+    pub file: PathBuf,
+    /// The images used by the level's tilesets, relative to the working directory
+    pub tileset_images: Vec<PathBuf>,
+    /// The size of the map, in tiles
+    pub size: Vector2<f32>,
+    pub collision: Vec<CollisionGeometry>,
+    /// Platforms which only block bodies landing on them from above
+    pub one_way_platforms: Vec<Aabb2<f32>>,
     pub easel_rect: Aabb2<f32>,
+    /// The areas the player escapes through to finish the level
+    pub exits: Vec<Aabb2<f32>>,
+    /// The paintings hanging on the walls, which the player swaps for replicas
+    pub paintings: Vec<PaintingInfo>,
+    /// The artwork for each painting, in the same order as `paintings`. Empty until the level is
+    /// loaded with `load`.
+    pub painting_images: Vec<Image>,
+    pub doors: Vec<DoorInfo>,
+    /// The keys lying around the level, which unlock doors
+    pub keys: Vec<KeyInfo>,
+    /// Places the player can hide in, out of sight of the guards
+    pub hiding_spots: Vec<Aabb2<f32>>,
+    /// Dark areas where the guards are slower to notice the player
+    pub shadows: Vec<Aabb2<f32>>,
+    /// Areas the player can climb up and down
+    pub ladders: Vec<Aabb2<f32>>,
+    /// Areas which raise the alarm when the player walks through them
+    pub tripwires: Vec<Aabb2<f32>>,
     pub player_spawn: Point2<f32>,
     pub guards: Vec<GuardInfo>,
     pub guard_jump_boxes: Vec<GuardJumpBox>,
     pub guard_turn_around: Vec<Aabb2<f32>>,
+    /// The surfaces guards can walk along and how to get between them
+    pub nav: NavGraph,
 }
 
+/// The default walking force of a patrolling guard
+const DEFAULT_PATROL_SPEED: f32 = 7.0;
+/// The default walking force of a guard chasing the player
+const DEFAULT_CHASE_SPEED: f32 = 25.0;
+/// How far a guard can see by default, in tiles
+const DEFAULT_VISION_RANGE: f32 = 12.0;
+/// The default width of a guard's vision cone, in degrees
+const DEFAULT_VISION_ANGLE: f32 = 120.0;
+/// The default width of a guard's peripheral vision, in degrees
+const DEFAULT_PERIPHERAL_ANGLE: f32 = 200.0;
+/// How far a guard's peripheral vision reaches by default, in tiles
+const DEFAULT_PERIPHERAL_RANGE: f32 = 4.0;
+/// How close the player has to be for a guard to notice them from any direction by default, in
+/// tiles
+const DEFAULT_NEAR_RANGE: f32 = 1.5;
+/// How long it takes a guard's suspicion to fill by default, in seconds
+const DEFAULT_REACTION_TIME: f32 = 0.8;
+/// How likely a guard is to spot a forgery by default. The chance of spotting one is this times
+/// how inaccurate the forgery is.
+const DEFAULT_PERCEPTIVENESS: f32 = 1.0;
+/// How often a guard works out its path again by default, in seconds
+const DEFAULT_REPATH_INTERVAL: f32 = 1.0;
+/// How far below straight ahead a security camera points by default, in degrees
+const DEFAULT_CAMERA_PITCH: f32 = 35.0;
+/// How far a security camera sweeps back and forth by default, in degrees
+const DEFAULT_CAMERA_SWEEP_ANGLE: f32 = 50.0;
+/// How long a security camera takes to sweep there and back by default, in seconds
+const DEFAULT_CAMERA_SWEEP_TIME: f32 = 6.0;
+
+/// The object types guards are spawned from, along with the kind of guard each spawns.
+/// `guard_spawn` objects pick their kind with the `guard_type` property instead.
+const GUARD_SPAWN_TYPES: &[(&str, Option<GuardKind>)] = &[
+    ("guard_spawn", None),
+    ("elite_guard_spawn", Some(GuardKind::Elite)),
+    ("dog_spawn", Some(GuardKind::Dog)),
+    ("security_camera", Some(GuardKind::Camera)),
+];
+
+/// Everything needed to spawn a guard, read from a `guard_spawn`, `elite_guard_spawn`,
+/// `dog_spawn` or `security_camera` object.
+///
+/// The spawn object can override any of the defaults for its kind with the custom properties
+/// `facing` (`left` or `right`), `patrol_speed`, `chase_speed`, `vision_range` (in tiles),
+/// `vision_angle` (the full width of the cone in degrees), `peripheral_angle` (in degrees),
+/// `peripheral_range` (in tiles), `near_range` (in tiles), `reaction_time` (in seconds),
+/// `perceptiveness`, `hearing`, `flashlight`, `repath_interval` (in seconds), `pitch` (in
+/// degrees), `sweep_angle` (in degrees) and `sweep_time` (in seconds).
 #[derive(Debug, Clone)]
 pub struct GuardInfo {
     pub spawn: Point2<f32>,
+    /// The route this guard walks while patrolling. Guards without one bounce between
+    /// `turn_around` boxes instead.
+    pub route: Option<PatrolRoute>,
+    pub kind: GuardKind,
+    /// The direction the guard faces when the level starts
+    pub facing: Direction,
+    pub patrol_speed: f32,
+    pub chase_speed: f32,
+    /// How far the guard can see, in tiles
+    pub vision_range: f32,
+    /// The width of the guard's vision cone, in radians
+    pub vision_angle: f32,
+    /// The width of the guard's peripheral vision, in radians. It is wider than the main cone but
+    /// doesn't reach as far, and the guard reacts slower to what it sees there.
+    pub peripheral_angle: f32,
+    /// How far the guard's peripheral vision reaches, in tiles
+    pub peripheral_range: f32,
+    /// How close the player has to be for the guard to notice them whichever way it's facing
+    pub near_range: f32,
+    /// How long it takes the guard's suspicion to fill while it watches the player up close and
+    /// out in the open, in seconds
+    pub reaction_time: f32,
+    /// How good the guard is at spotting forgeries. The chance of spotting one is this times
+    /// `1 - accuracy` of the replica.
+    pub perceptiveness: f32,
+    /// How much further away than usual the guard can hear noises from. Guards which can't hear
+    /// at all have this set to 0.
+    pub hearing: f32,
+    /// Whether the guard carries a flashlight, which lets it see into the shadows as well as
+    /// anywhere else
+    pub flashlight: bool,
+    /// How often the guard works out its path again while it's heading somewhere, in seconds
+    pub repath_interval: f32,
+    /// How far below straight ahead the guard looks, in radians
+    pub pitch: f32,
+    /// How far the guard sweeps its gaze up and down around `pitch`, in radians. Only cameras
+    /// sweep.
+    pub sweep_angle: f32,
+    /// How long the guard takes to sweep its gaze there and back, in seconds
+    pub sweep_time: f32,
+}
+
+impl GuardInfo {
+    fn from_object(spawn: &Object, kind: Option<GuardKind>, paths: &[&Object]) -> Self {
+        let kind = kind.unwrap_or_else(|| {
+            string_property(spawn, "guard_type")
+                .and_then(GuardKind::from_name)
+                .unwrap_or(GuardKind::Standard)
+        });
+
+        let facing = match string_property(spawn, "facing") {
+            Some("right") => Direction::Right,
+            _ => Direction::Left,
+        };
+
+        let defaults = GuardInfo::defaults(kind);
+        let degrees = |name: &str, default: f32| {
+            float_property(spawn, name).map_or(default, f32::to_radians)
+        };
+
+        GuardInfo {
+            spawn: Point2::new(spawn.x / 16.0, spawn.y / 16.0),
+            route: Level::build_route(spawn, paths),
+            kind,
+            facing,
+            patrol_speed: float_property(spawn, "patrol_speed").unwrap_or(defaults.patrol_speed),
+            chase_speed: float_property(spawn, "chase_speed").unwrap_or(defaults.chase_speed),
+            vision_range: float_property(spawn, "vision_range").unwrap_or(defaults.vision_range),
+            vision_angle: degrees("vision_angle", defaults.vision_angle),
+            peripheral_angle: degrees("peripheral_angle", defaults.peripheral_angle),
+            peripheral_range: float_property(spawn, "peripheral_range")
+                .unwrap_or(defaults.peripheral_range),
+            near_range: float_property(spawn, "near_range").unwrap_or(defaults.near_range),
+            reaction_time: float_property(spawn, "reaction_time").unwrap_or(defaults.reaction_time),
+            perceptiveness: float_property(spawn, "perceptiveness")
+                .unwrap_or(defaults.perceptiveness),
+            hearing: float_property(spawn, "hearing").unwrap_or(defaults.hearing),
+            flashlight: bool_property(spawn, "flashlight").unwrap_or(defaults.flashlight),
+            repath_interval: float_property(spawn, "repath_interval")
+                .unwrap_or(defaults.repath_interval),
+            pitch: degrees("pitch", defaults.pitch),
+            sweep_angle: degrees("sweep_angle", defaults.sweep_angle),
+            sweep_time: float_property(spawn, "sweep_time").unwrap_or(defaults.sweep_time),
+        }
+    }
+
+    /// The settings a guard of the given kind has unless its spawn object overrides them. This
+    /// doesn't fill in where the guard is or which way it faces.
+    fn defaults(kind: GuardKind) -> GuardInfo {
+        let standard = GuardInfo {
+            spawn: Point2::new(0.0, 0.0),
+            route: None,
+            kind,
+            facing: Direction::Left,
+            patrol_speed: DEFAULT_PATROL_SPEED,
+            chase_speed: DEFAULT_CHASE_SPEED,
+            vision_range: DEFAULT_VISION_RANGE,
+            vision_angle: DEFAULT_VISION_ANGLE.to_radians(),
+            peripheral_angle: DEFAULT_PERIPHERAL_ANGLE.to_radians(),
+            peripheral_range: DEFAULT_PERIPHERAL_RANGE,
+            near_range: DEFAULT_NEAR_RANGE,
+            reaction_time: DEFAULT_REACTION_TIME,
+            perceptiveness: DEFAULT_PERCEPTIVENESS,
+            hearing: 1.0,
+            flashlight: false,
+            repath_interval: DEFAULT_REPATH_INTERVAL,
+            pitch: 0.0,
+            sweep_angle: 0.0,
+            sweep_time: DEFAULT_CAMERA_SWEEP_TIME,
+        };
+
+        match kind {
+            GuardKind::Standard => standard,
+            GuardKind::Elite => GuardInfo {
+                patrol_speed: DEFAULT_PATROL_SPEED * 1.2,
+                chase_speed: DEFAULT_CHASE_SPEED * 1.2,
+                vision_range: DEFAULT_VISION_RANGE * 1.5,
+                reaction_time: DEFAULT_REACTION_TIME * 0.5,
+                perceptiveness: DEFAULT_PERCEPTIVENESS * 1.5,
+                flashlight: true,
+                repath_interval: DEFAULT_REPATH_INTERVAL * 0.25,
+                ..standard
+            },
+            GuardKind::Dog => GuardInfo {
+                patrol_speed: DEFAULT_PATROL_SPEED * 1.3,
+                chase_speed: DEFAULT_CHASE_SPEED * 1.6,
+                vision_range: DEFAULT_VISION_RANGE * 0.4,
+                vision_angle: (DEFAULT_VISION_ANGLE * 0.75).to_radians(),
+                peripheral_range: DEFAULT_PERIPHERAL_RANGE * 0.5,
+                near_range: DEFAULT_NEAR_RANGE * 1.5,
+                reaction_time: DEFAULT_REACTION_TIME * 1.5,
+                perceptiveness: 0.0,
+                hearing: 2.5,
+                repath_interval: DEFAULT_REPATH_INTERVAL * 0.5,
+                ..standard
+            },
+            GuardKind::Camera => GuardInfo {
+                patrol_speed: 0.0,
+                chase_speed: 0.0,
+                vision_angle: (DEFAULT_VISION_ANGLE * 0.5).to_radians(),
+                peripheral_angle: 0.0,
+                peripheral_range: 0.0,
+                near_range: 0.0,
+                perceptiveness: 0.0,
+                hearing: 0.0,
+                pitch: DEFAULT_CAMERA_PITCH.to_radians(),
+                sweep_angle: DEFAULT_CAMERA_SWEEP_ANGLE.to_radians(),
+                ..standard
+            },
+        }
+    }
+}
+
+/// The kind of guard, picked by the type of its spawn object or the `guard_type` property of a
+/// `guard_spawn` object. This picks the defaults for any settings which the spawn object doesn't
+/// override, and how the guard behaves.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GuardKind {
+    Standard,
+    /// A faster guard which sees further with a flashlight, reacts quicker, keeps better track of
+    /// where it's going and is better at spotting forgeries
+    Elite,
+    /// A fast guard with good hearing and bad eyesight, which barks to bring other guards over
+    /// while it chases the player. It can't open doors or spot forgeries.
+    Dog,
+    /// A security camera fixed in place, which sweeps its gaze back and forth and raises the
+    /// alarm when it spots the player
+    Camera,
+}
+
+impl GuardKind {
+    fn from_name(name: &str) -> Option<GuardKind> {
+        match name {
+            "standard" => Some(GuardKind::Standard),
+            "elite" => Some(GuardKind::Elite),
+            "dog" => Some(GuardKind::Dog),
+            "camera" => Some(GuardKind::Camera),
+            _ => None,
+        }
+    }
+}
+
+/// A list of waypoints loaded from `guard_pathfind` objects
+#[derive(Debug, Clone)]
+pub struct PatrolRoute {
+    pub waypoints: Vec<Waypoint>,
+    /// Whether the guard goes from the last waypoint back to the first one rather than walking
+    /// the route in reverse
+    pub looping: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Waypoint {
+    pub pos: Point2<f32>,
+    /// How long the guard stands at this waypoint, in seconds
+    pub wait: f32,
+    /// Whether the guard turns around to look behind itself while waiting here
+    pub look_around: bool,
+}
+
+/// A spot on a wall where a painting hangs, read from a `painting` object. The `painting` custom
+/// property is the file name of the artwork in `resources/paintings`.
+#[derive(Debug, Clone)]
+pub struct PaintingInfo {
+    /// The area of wall the painting covers, in tiles
+    pub bounds: Aabb2<f32>,
+    pub painting: String,
+}
+
+impl PaintingInfo {
+    /// The path of the artwork, relative to the working directory
+    pub fn path(&self) -> PathBuf {
+        Path::new("resources/paintings").join(&self.painting)
+    }
+}
+
+/// Whether a door can be walked through, set by the `state` property of a `door` object
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DoorState {
+    Open,
+    /// Shut, but anyone can open it
+    Closed,
+    /// Shut, and only the player can open it once they have its key
+    Locked,
+}
+
+impl DoorState {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "open" => Some(DoorState::Open),
+            "closed" => Some(DoorState::Closed),
+            "locked" => Some(DoorState::Locked),
+            _ => None,
+        }
+    }
+}
+
+/// A door read from a `door` object. It blocks movement and sight while it is shut.
+#[derive(Debug, Clone)]
+pub struct DoorInfo {
+    pub bounds: Aabb2<f32>,
+    /// How the door starts out, which is closed if it isn't set
+    pub state: DoorState,
+    /// The `key` property of the key which unlocks this door
+    pub key: Option<String>,
+    /// Whether the door locks itself while the level is in lockdown, set by the `lockdown`
+    /// property
+    pub lockdown: bool,
+}
+
+/// A key read from a `key` object, which unlocks doors with the same `key` property
+#[derive(Debug, Clone)]
+pub struct KeyInfo {
+    pub bounds: Aabb2<f32>,
+    pub key: String,
 }
 
 #[derive(Clone)]
@@ -37,67 +385,158 @@ impl GuardJumpBox {
         }
     }
 
-    pub fn get_direction(&self) -> ::entity::components::Direction {
+    pub fn get_direction(&self) -> Direction {
         match self {
-            GuardJumpBox::Left(_) => ::entity::components::Direction::Left,
-            GuardJumpBox::Right(_) => ::entity::components::Direction::Right,
+            GuardJumpBox::Left(_) => Direction::Left,
+            GuardJumpBox::Right(_) => Direction::Right,
         }
     }
 }
 
 impl Level {
-    /// Loads a level from a tiled map
-    ///
-    /// ## Panicking
-    ///
-    /// Panics if an object named `easel` is not found in the object layer named `objects`
-    pub fn load_from(file: File, ctx: &mut ggez::Context) -> GameResult<Self> {
-        use tiled::ObjectShape;
+    /// Loads a level from the tiled map at the given path
+    pub fn load(path: &Path, ctx: &mut ggez::Context) -> Result<Self, LevelError> {
+        let file = File::open(path).map_err(|error| LevelError::Io {
+            file: path.to_owned(),
+            error,
+        })?;
+        let map = tiled::parse(BufReader::new(file)).map_err(|error| LevelError::Parse {
+            file: path.to_owned(),
+            error,
+        })?;
 
-        let reader = BufReader::new(file);
-        let map = tiled::parse(reader).expect("Error reading map file!");
+        let mut level = Level::from_map(&map, path)?;
 
         // Load tilesets
-        let tilesets = map.tilesets
-            .iter()
-            .map(|set| {
-                let set_source = &set.images[0];
-                let image = Image::new(ctx, format!("/{}", set_source.source))
-                    .expect("Error reading tileset image!");
-                LoadedTileset {
-                    batch: Level::build_batch(&map, image.clone(), &set, &set_source),
+        for set in &map.tilesets {
+            let set_source = set.images.first().ok_or_else(|| LevelError::Asset {
+                file: path.to_owned(),
+                path: set.name.clone(),
+                error: "tileset has no image".to_owned(),
+            })?;
+            let image = Image::new(ctx, format!("/{}", set_source.source)).map_err(|error| {
+                LevelError::Asset {
+                    file: path.to_owned(),
+                    path: set_source.source.clone(),
+                    error: error.to_string(),
                 }
-            })
+            })?;
+
+            level
+                .tileset_images
+                .push(Path::new("resources").join(&set_source.source));
+            level.tilesets.push(LoadedTileset {
+                batch: Level::build_batch(&map, image, &set, &set_source),
+            });
+        }
+
+        for painting in &level.paintings {
+            let image = Image::new(ctx, format!("/paintings/{}", painting.painting)).map_err(
+                |error| LevelError::Asset {
+                    file: path.to_owned(),
+                    path: painting.painting.clone(),
+                    error: error.to_string(),
+                },
+            )?;
+            level.painting_images.push(image);
+        }
+
+        Ok(level)
+    }
+
+    /// Reads the level layout out of a tiled map, without loading any of its images. The level
+    /// will not draw anything until its tilesets are loaded by `load`. `file` is only used to
+    /// describe where errors came from.
+    pub fn from_map(map: &Map, file: &Path) -> Result<Self, LevelError> {
+        use tiled::ObjectShape;
+
+        // Generate collision for tiles marked as `solid` in the tileset. Objects of type `clear`
+        // in the collision layer remove the generated collision from the tiles they cover, and
+        // any other objects there are added on top of it.
+        let mut solid_tiles = TileGrid::from_property(map, "solid");
+        for object in Level::find_objects_by_type(map, "collision", "clear") {
+            if let ObjectShape::Rect { width, height } = object.shape {
+                solid_tiles.clear(&Aabb2::new(
+                    Point2::new(object.x / 16.0, object.y / 16.0),
+                    Point2::new((object.x + width) / 16.0, (object.y + height) / 16.0),
+                ));
+            }
+        }
+
+        let mut collision: Vec<CollisionGeometry> = solid_tiles
+            .merged_rects()
+            .into_iter()
+            .map(CollisionGeometry::Rect)
             .collect();
 
         // Get objects
-        let collision_rects = map.object_groups
+        collision.extend(map.object_groups
             .iter()
             .filter(|group| group.name == "collision")
             .flat_map(|group| &group.objects)
-            .filter_map(|object| match object.shape {
-                ObjectShape::Rect { width, height } => Some(Aabb2::new(
-                    Point2::new(object.x / 16.0, object.y / 16.0),
-                    Point2::new((object.x + width) / 16.0, (object.y + height) / 16.0),
-                )),
-                _ => None,
+            .filter(|object| object.obj_type != "clear" && object.obj_type != "one_way")
+            .map(|object| {
+                let to_points = |points: &[(f32, f32)]| {
+                    points
+                        .iter()
+                        .map(|&(x, y)| Point2::new((object.x + x) / 16.0, (object.y + y) / 16.0))
+                        .collect::<Vec<_>>()
+                };
+
+                match object.shape {
+                    ObjectShape::Rect { width, height } => Ok(vec![CollisionGeometry::Rect(Aabb2::new(
+                        Point2::new(object.x / 16.0, object.y / 16.0),
+                        Point2::new((object.x + width) / 16.0, (object.y + height) / 16.0),
+                    ))]),
+                    ObjectShape::Ellipse { width, height } => Ok(vec![CollisionGeometry::ellipse(Aabb2::new(
+                        Point2::new(object.x / 16.0, object.y / 16.0),
+                        Point2::new((object.x + width) / 16.0, (object.y + height) / 16.0),
+                    ))]),
+                    ObjectShape::Polygon { ref points } => CollisionGeometry::polygon(&to_points(points))
+                        .ok_or_else(|| LevelError::BadShape {
+                            file: file.to_owned(),
+                            object: object.id,
+                            name: object.name.clone(),
+                            expected: "simple polygon",
+                        }),
+                    ObjectShape::Polyline { ref points } => Ok(CollisionGeometry::polyline(&to_points(points))),
+                }
             })
-            .collect();
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flat_map(|pieces| pieces));
+
+        // One way platforms come from tiles marked as `one_way` and from `one_way` objects in the
+        // collision layer
+        let mut one_way_platforms = TileGrid::from_property(map, "one_way").merged_rows();
+        one_way_platforms.extend(
+            Level::find_objects_by_type(map, "collision", "one_way")
+                .iter()
+                .filter_map(|object| match object.shape {
+                    ObjectShape::Rect { width, height } => Some(Aabb2::new(
+                        Point2::new(object.x / 16.0, object.y / 16.0),
+                        Point2::new((object.x + width) / 16.0, (object.y + height) / 16.0),
+                    )),
+                    _ => None,
+                }),
+        );
 
-        let player_spawn = util::take(Level::find_object_points(&map, "objects", "player_spawn"))
-            .unwrap_or_else(|| Point2::new(0.0, 0.0));
+        let player_spawn = util::take(Level::find_object_points(map, "objects", "player_spawn"))
+            .ok_or_else(|| LevelError::MissingObject {
+                file: file.to_owned(),
+                name: "player_spawn",
+            })?;
 
-        let guard_spawns = Level::find_objects_by_type(&map, "objects", "guard_spawn");
+        let guard_spawns = Level::find_guard_spawns(map);
+        let guard_paths = Level::find_objects_by_type(map, "objects", "guard_pathfind");
 
         let mut guards = Vec::with_capacity(guard_spawns.len());
 
-        for spawn in guard_spawns {
-            guards.push(GuardInfo {
-                spawn: Point2::new(spawn.x / 16.0, spawn.y / 16.0),
-            });
+        for (spawn, kind) in guard_spawns {
+            guards.push(GuardInfo::from_object(spawn, kind, &guard_paths));
         }
 
-        let guard_turn_around = Level::find_objects_by_type(&map, "objects", "turn_around")
+        let guard_turn_around = Level::find_objects_by_type(map, "objects", "turn_around")
             .iter()
             .filter_map(|object| match object.shape {
                 ObjectShape::Rect { width, height } => Some(Aabb2::new(
@@ -109,15 +548,15 @@ impl Level {
             .collect::<Vec<Aabb2<f32>>>();
 
         let mut guard_jump_boxes: Vec<_> =
-            Level::find_objects_by_type(&map, "objects", "jump_left")
+            Level::find_objects_by_type(map, "objects", "jump_left")
                 .into_iter()
                 .map(|o| (false, o))
                 .collect();
 
         guard_jump_boxes.append(
-            &mut Level::find_objects_by_type(&map, "objects", "jump_right")
+            &mut Level::find_objects_by_type(map, "objects", "jump_right")
                 .into_iter()
-                .map(|o| (false, o))
+                .map(|o| (true, o))
                 .collect(),
         );
 
@@ -139,27 +578,160 @@ impl Level {
             })
             .collect::<Vec<GuardJumpBox>>();
 
-        let easel_rect = util::take(Level::find_objects(&map, "objects", "easel"))
-            .and_then(|object| match object.shape {
-                ObjectShape::Rect { width, height } => Some(Aabb2::new(
-                    Point2::new(object.x / 16.0, object.y / 16.0),
-                    Point2::new((object.x + width) / 16.0, (object.y + height) / 16.0),
-                )),
-                _ => None,
-            })
-            .expect("Level requires an object named `easel` in the `objects` layer!");
+        let easel = util::take(Level::find_objects(map, "objects", "easel")).ok_or_else(|| {
+            LevelError::MissingObject {
+                file: file.to_owned(),
+                name: "easel",
+            }
+        })?;
+
+        let easel_rect = match easel.shape {
+            ObjectShape::Rect { width, height } => Aabb2::new(
+                Point2::new(easel.x / 16.0, easel.y / 16.0),
+                Point2::new((easel.x + width) / 16.0, (easel.y + height) / 16.0),
+            ),
+            _ => {
+                return Err(LevelError::BadShape {
+                    file: file.to_owned(),
+                    object: easel.id,
+                    name: easel.name.clone(),
+                    expected: "rectangle",
+                })
+            }
+        };
+
+        let exits = Level::find_objects_by_type(map, "objects", "exit")
+            .into_iter()
+            .map(|exit| rect_bounds(exit, file))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut paintings = Vec::new();
+        for object in Level::find_objects_by_type(map, "objects", "painting") {
+            paintings.push(PaintingInfo {
+                bounds: rect_bounds(object, file)?,
+                painting: required_string_property(object, "painting", file)?,
+            });
+        }
+
+        let mut doors = Vec::new();
+        for object in Level::find_objects_by_type(map, "objects", "door") {
+            doors.push(DoorInfo {
+                bounds: rect_bounds(object, file)?,
+                state: string_property(object, "state")
+                    .and_then(DoorState::from_name)
+                    .unwrap_or(DoorState::Closed),
+                key: string_property(object, "key").map(str::to_owned),
+                lockdown: bool_property(object, "lockdown").unwrap_or(false),
+            });
+        }
+
+        let mut keys = Vec::new();
+        for object in Level::find_objects_by_type(map, "objects", "key") {
+            keys.push(KeyInfo {
+                bounds: rect_bounds(object, file)?,
+                key: required_string_property(object, "key", file)?,
+            });
+        }
+
+        let hiding_spots = Level::find_objects_by_type(map, "objects", "hiding_spot")
+            .into_iter()
+            .map(|spot| rect_bounds(spot, file))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let shadows = Level::find_objects_by_type(map, "objects", "shadow")
+            .into_iter()
+            .map(|shadow| rect_bounds(shadow, file))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let ladders = Level::find_objects_by_type(map, "objects", "ladder")
+            .into_iter()
+            .map(|ladder| rect_bounds(ladder, file))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let tripwires = Level::find_objects_by_type(map, "objects", "tripwire")
+            .into_iter()
+            .map(|tripwire| rect_bounds(tripwire, file))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let nav = NavGraph::build(&collision, &one_way_platforms, ::GUARD_SIZE);
 
         Ok(Level {
-            tilesets,
-            collision_rects,
+            tilesets: Vec::new(),
+            file: file.to_owned(),
+            tileset_images: Vec::new(),
+            size: Vector2::new(
+                (map.width * map.tile_width) as f32 / 16.0,
+                (map.height * map.tile_height) as f32 / 16.0,
+            ),
+            collision,
+            one_way_platforms,
             easel_rect,
+            exits,
+            paintings,
+            doors,
+            keys,
+            hiding_spots,
+            shadows,
+            ladders,
+            tripwires,
+            painting_images: Vec::new(),
             player_spawn,
             guards,
             guard_jump_boxes,
             guard_turn_around,
+            nav,
         })
     }
 
+    /// Builds the patrol route for a guard from the `guard_pathfind` objects linked to it.
+    ///
+    /// A path object is linked to a guard either by sharing its name, by the path having a `guard`
+    /// property naming the guard, or by the guard having a `route` property naming the path.
+    /// Polylines contribute all of their points in order, polygons also make the route loop, and
+    /// any other shape contributes a single waypoint at its position. When several objects are
+    /// linked they are joined in the order given by their `order` property.
+    fn build_route(spawn: &Object, paths: &[&Object]) -> Option<PatrolRoute> {
+        use tiled::ObjectShape;
+
+        let mut linked: Vec<&Object> = paths
+            .iter()
+            .cloned()
+            .filter(|path| is_route_of(path, spawn))
+            .collect();
+
+        linked.sort_by_key(|path| int_property(path, "order").unwrap_or(0));
+
+        let mut waypoints = Vec::new();
+        let mut looping = bool_property(spawn, "loop").unwrap_or(false);
+
+        for path in linked {
+            let wait = float_property(path, "wait").unwrap_or(0.0);
+            let look_around = bool_property(path, "look_around").unwrap_or(false);
+            let waypoint = |x: f32, y: f32| Waypoint {
+                pos: Point2::new((path.x + x) / 16.0, (path.y + y) / 16.0),
+                wait,
+                look_around,
+            };
+
+            match path.shape {
+                ObjectShape::Polyline { ref points } => {
+                    waypoints.extend(points.iter().map(|&(x, y)| waypoint(x, y)));
+                }
+                ObjectShape::Polygon { ref points } => {
+                    waypoints.extend(points.iter().map(|&(x, y)| waypoint(x, y)));
+                    looping = true;
+                }
+                _ => waypoints.push(waypoint(0.0, 0.0)),
+            }
+        }
+
+        if waypoints.is_empty() {
+            None
+        } else {
+            Some(PatrolRoute { waypoints, looping })
+        }
+    }
+
     fn find_object_points_by_type(
         map: &Map,
         group_name: &'static str,
@@ -195,6 +767,18 @@ impl Level {
             .collect()
     }
 
+    /// Finds every object which spawns a guard, along with the kind of guard its type spawns
+    fn find_guard_spawns(map: &Map) -> Vec<(&Object, Option<GuardKind>)> {
+        GUARD_SPAWN_TYPES
+            .iter()
+            .flat_map(|&(object_type, kind)| {
+                Level::find_objects_by_type(map, "objects", object_type)
+                    .into_iter()
+                    .map(move |spawn| (spawn, kind))
+            })
+            .collect()
+    }
+
     fn find_objects_by_type<'a>(
         map: &'a Map,
         group_name: &'static str,
@@ -262,7 +846,7 @@ impl Level {
         batch
     }
 
-    pub fn render(&mut self, ctx: &mut ggez::Context) {
+    pub fn render(&mut self, ctx: &mut ggez::Context, camera: &Camera) {
         for tileset in &self.tilesets {
             tileset
                 .batch
@@ -270,9 +854,9 @@ impl Level {
                     ctx,
                     DrawParam {
                         src: Rect::one(),
-                        dest: graphics::Point2::new(0.0, 0.0),
+                        dest: camera.to_screen(Point2::new(0.0, 0.0)),
                         rotation: 0.0,
-                        scale: graphics::Point2::new(::GLOBAL_SCALE, ::GLOBAL_SCALE),
+                        scale: graphics::Point2::new(1.0, 1.0),
                         offset: graphics::Point2::new(0.0, 0.0),
                         shear: graphics::Point2::new(0.0, 0.0),
                         color: None,
@@ -286,3 +870,72 @@ impl Level {
 struct LoadedTileset {
     pub batch: SpriteBatch,
 }
+
+/// Whether a `guard_pathfind` object is part of the route of the given guard spawn
+fn is_route_of(path: &Object, spawn: &Object) -> bool {
+    match (string_property(spawn, "route"), string_property(path, "guard")) {
+        (Some(route), _) => path.name == route,
+        (None, Some(guard)) => guard == spawn.name,
+        (None, None) => !path.name.is_empty() && path.name == spawn.name,
+    }
+}
+
+/// The bounds of a rectangle object, in tiles
+fn rect_bounds(object: &Object, file: &Path) -> Result<Aabb2<f32>, LevelError> {
+    match object.shape {
+        tiled::ObjectShape::Rect { width, height } => Ok(Aabb2::new(
+            Point2::new(object.x / 16.0, object.y / 16.0),
+            Point2::new((object.x + width) / 16.0, (object.y + height) / 16.0),
+        )),
+        _ => Err(LevelError::BadShape {
+            file: file.to_owned(),
+            object: object.id,
+            name: object.name.clone(),
+            expected: "rectangle",
+        }),
+    }
+}
+
+fn required_string_property(
+    object: &Object,
+    property: &'static str,
+    file: &Path,
+) -> Result<String, LevelError> {
+    string_property(object, property)
+        .map(str::to_owned)
+        .ok_or_else(|| LevelError::MissingProperty {
+            file: file.to_owned(),
+            object: object.id,
+            name: object.name.clone(),
+            property,
+        })
+}
+
+fn string_property<'a>(object: &'a Object, name: &str) -> Option<&'a str> {
+    match object.properties.get(name) {
+        Some(&PropertyValue::StringValue(ref value)) => Some(value),
+        _ => None,
+    }
+}
+
+fn float_property(object: &Object, name: &str) -> Option<f32> {
+    match object.properties.get(name) {
+        Some(&PropertyValue::FloatValue(value)) => Some(value),
+        Some(&PropertyValue::IntValue(value)) => Some(value as f32),
+        _ => None,
+    }
+}
+
+fn int_property(object: &Object, name: &str) -> Option<i32> {
+    match object.properties.get(name) {
+        Some(&PropertyValue::IntValue(value)) => Some(value),
+        _ => None,
+    }
+}
+
+fn bool_property(object: &Object, name: &str) -> Option<bool> {
+    match object.properties.get(name) {
+        Some(&PropertyValue::BoolValue(value)) => Some(value),
+        _ => None,
+    }
+}
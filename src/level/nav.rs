@@ -0,0 +1,525 @@
+use cgmath::{Point2, Vector2};
+use collision::Aabb2;
+use ord_subset::OrdSubsetIterExt;
+use super::{CollisionGeometry, DEFAULT_PATROL_SPEED};
+
+/// How close in height two surfaces have to be to be joined into one, in tiles
+const SURFACE_TOLERANCE: f32 = 0.05;
+/// How far above or below a surface something can be and still count as standing on it, in tiles
+const STAND_TOLERANCE: f32 = 0.3;
+/// How long a step the jumps are simulated with. The physics is tuned for 60 frames per second.
+const SIMULATION_STEP: f32 = 1.0 / 60.0;
+/// The longest a simulated jump can stay in the air for, in seconds
+const MAX_AIR_TIME: f32 = 2.0;
+/// How much the guard's body is shrunk by when checking jumps, so that brushing past corners and
+/// touching the surfaces it lands on doesn't count as hitting something
+const BODY_MARGIN: f32 = 0.05;
+/// The extra cost of jumping, so guards prefer to walk when it's not much further
+const JUMP_COST: f32 = 2.0;
+/// The extra cost of falling off a ledge
+const FALL_COST: f32 = 0.5;
+
+/// How a guard gets from one surface to another
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NavEdgeKind {
+    /// Jumps from the take off point and steers towards the landing point
+    Jump,
+    /// Walks off the edge at the take off point and drops down
+    Fall,
+}
+
+/// A surface a guard can walk along, in tiles
+#[derive(Debug, Copy, Clone)]
+pub struct NavNode {
+    pub left: f32,
+    pub right: f32,
+    /// The height of the top of the surface
+    pub y: f32,
+}
+
+/// A way of getting from one surface to another
+#[derive(Debug, Copy, Clone)]
+pub struct NavEdge {
+    pub kind: NavEdgeKind,
+    /// The index of the node this edge leads to
+    pub to: usize,
+    /// Where the guard leaves from, on the node the edge starts at
+    pub from_x: f32,
+    /// Where the guard aims to land, on the node the edge leads to
+    pub to_x: f32,
+    pub cost: f32,
+}
+
+/// The surfaces guards can walk along and the jumps and falls between them, built from the
+/// level's collision geometry
+#[derive(Debug, Clone)]
+pub struct NavGraph {
+    pub nodes: Vec<NavNode>,
+    /// The edges leading out of each node, in the same order as `nodes`
+    pub edges: Vec<Vec<NavEdge>>,
+}
+
+impl NavGraph {
+    /// Builds the graph for a body of the given size from the solid geometry and the one way
+    /// platforms. Only the tops of rects and platforms are walked along, but every shape gets in
+    /// the way of walking, jumping and falling.
+    pub fn build(solid: &[CollisionGeometry], one_way: &[Aabb2<f32>], body: (f32, f32)) -> Self {
+        let nodes = surfaces(solid, one_way, body);
+        let trajectory = simulate_jump();
+
+        let edges = (0..nodes.len())
+            .map(|from| {
+                let mut edges = fall_edges(&nodes, from, solid, body);
+                edges.extend(
+                    (0..nodes.len())
+                        .filter(|&to| to != from)
+                        .filter_map(|to| jump_edge(&nodes, from, to, solid, body, &trajectory)),
+                );
+                edges
+            })
+            .collect();
+
+        NavGraph { nodes, edges }
+    }
+
+    /// The closest surface under the given point, or the one a body with its feet at the point is
+    /// standing on
+    pub fn node_below(&self, point: Point2<f32>) -> Option<usize> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|&(_index, node)| {
+                node.left <= point.x && point.x <= node.right && node.y >= point.y - STAND_TOLERANCE
+            })
+            .ord_subset_min_by_key(|&(_index, node)| node.y - point.y)
+            .map(|(index, _node)| index)
+    }
+
+    /// Whether a body with its feet at the given point is standing on the given node
+    pub fn standing_on(&self, feet: Point2<f32>, node: usize) -> bool {
+        match self.nodes.get(node) {
+            Some(node) => {
+                node.left <= feet.x && feet.x <= node.right
+                    && (node.y - feet.y).abs() <= STAND_TOLERANCE
+            }
+            None => false,
+        }
+    }
+
+    /// Finds the cheapest way to get from `from` to `to` with A*, as the edges to follow in
+    /// order. Both points are dropped down onto the surface below them first. The path is empty
+    /// if they're already on the same surface, and `None` if there is no way there.
+    pub fn find_path(&self, from: Point2<f32>, to: Point2<f32>) -> Option<Vec<NavEdge>> {
+        let start = self.node_below(from)?;
+        let goal = self.node_below(to)?;
+
+        // The cheapest cost found to reach each node, where the guard would be standing on it,
+        // and the node and edge it was reached through
+        let mut cost = vec![None; self.nodes.len()];
+        let mut position = vec![from.x; self.nodes.len()];
+        let mut came_from: Vec<Option<(usize, usize)>> = vec![None; self.nodes.len()];
+        let mut closed = vec![false; self.nodes.len()];
+        let mut open = vec![start];
+        cost[start] = Some(0.0);
+
+        // Every tile moved sideways costs at least one, and every tile moved up or down at least
+        // a half (falling is the cheapest way), so this never overestimates. It's exact on the
+        // goal, where all that's left is walking over to `to`.
+        let estimate = |node: usize, x: f32, cost: f32| {
+            cost + (x - to.x).abs() + (self.nodes[node].y - self.nodes[goal].y).abs() * 0.5
+        };
+
+        loop {
+            let current = match open
+                .iter()
+                .cloned()
+                .ord_subset_min_by_key(|&node| {
+                    estimate(node, position[node], cost[node].unwrap_or(0.0))
+                }) {
+                Some(current) => current,
+                None => return None,
+            };
+
+            if current == goal {
+                let mut path = Vec::new();
+                let mut node = goal;
+                while let Some((previous, edge)) = came_from[node] {
+                    path.push(self.edges[previous][edge]);
+                    node = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            open.retain(|&node| node != current);
+            closed[current] = true;
+
+            let current_cost = cost[current].unwrap_or(0.0);
+            for (index, edge) in self.edges[current].iter().enumerate() {
+                if closed[edge.to] {
+                    continue;
+                }
+
+                let new_cost = current_cost + (position[current] - edge.from_x).abs() + edge.cost;
+                if cost[edge.to].map_or(true, |old| new_cost < old) {
+                    cost[edge.to] = Some(new_cost);
+                    position[edge.to] = edge.to_x;
+                    came_from[edge.to] = Some((current, index));
+                    if !open.contains(&edge.to) {
+                        open.push(edge.to);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Finds the tops of the rects and platforms which can be stood on, leaving out any parts without
+/// enough headroom for the body, and joins up surfaces which line up with each other
+fn surfaces(solid: &[CollisionGeometry], one_way: &[Aabb2<f32>], body: (f32, f32)) -> Vec<NavNode> {
+    let mut pieces: Vec<NavNode> = Vec::new();
+
+    let rects = solid.iter().filter_map(|geometry| match *geometry {
+        CollisionGeometry::Rect(ref aabb) => Some(aabb),
+        _ => None,
+    });
+
+    for top in rects.chain(one_way) {
+        let y = top.min.y;
+
+        // Cut out anything which would be inside of something else while standing there
+        let mut free = vec![(top.min.x, top.max.x)];
+        for blocker in solid {
+            let (min_x, max_x) = match blocker.x_extent(y - body.1, y - SURFACE_TOLERANCE) {
+                Some(extent) => extent,
+                None => continue,
+            };
+
+            free = free
+                .into_iter()
+                .flat_map(|(left, right)| {
+                    if max_x <= left || min_x >= right {
+                        vec![(left, right)]
+                    } else {
+                        vec![(left, min_x), (max_x, right)]
+                    }
+                })
+                .filter(|&(left, right)| right > left)
+                .collect();
+        }
+
+        pieces.extend(
+            free.into_iter()
+                .map(|(left, right)| NavNode { left, right, y }),
+        );
+    }
+
+    pieces.sort_by(|a, b| {
+        (a.y, a.left)
+            .partial_cmp(&(b.y, b.left))
+            .unwrap_or(::std::cmp::Ordering::Equal)
+    });
+
+    let mut nodes: Vec<NavNode> = Vec::new();
+    for piece in pieces {
+        if let Some(last) = nodes.last_mut() {
+            let same_height = (last.y - piece.y).abs() < SURFACE_TOLERANCE;
+            if same_height && piece.left <= last.right + SURFACE_TOLERANCE {
+                last.right = last.right.max(piece.right);
+                continue;
+            }
+        }
+        nodes.push(piece);
+    }
+
+    nodes.retain(|node| node.right - node.left >= body.0);
+    nodes
+}
+
+/// The bounds of a body with its feet at the given point, shrunk by `BODY_MARGIN`
+fn body_at(feet: Point2<f32>, body: (f32, f32)) -> Aabb2<f32> {
+    Aabb2::new(
+        Point2::new(feet.x - body.0 / 2.0 + BODY_MARGIN, feet.y - body.1 + BODY_MARGIN),
+        Point2::new(feet.x + body.0 / 2.0 - BODY_MARGIN, feet.y - BODY_MARGIN),
+    )
+}
+
+fn blocked(bounds: &Aabb2<f32>, solid: &[CollisionGeometry]) -> bool {
+    solid.iter().any(|blocker| blocker.intersects(bounds))
+}
+
+/// Edges for walking off either end of a surface and landing on whatever is below
+fn fall_edges(
+    nodes: &[NavNode],
+    from: usize,
+    solid: &[CollisionGeometry],
+    body: (f32, f32),
+) -> Vec<NavEdge> {
+    let node = nodes[from];
+    let mut edges = Vec::new();
+
+    for &(edge_x, direction) in &[(node.left, -1.0), (node.right, 1.0)] {
+        // Just past the edge, with the whole body hanging over it
+        let x = edge_x + direction * body.0 / 2.0;
+        if blocked(&body_at(Point2::new(x, node.y), body), solid) {
+            continue;
+        }
+
+        let landing = nodes
+            .iter()
+            .enumerate()
+            .filter(|&(_index, other)| {
+                other.y > node.y + SURFACE_TOLERANCE && other.left <= x && x <= other.right
+            })
+            .ord_subset_min_by_key(|&(_index, other)| other.y);
+
+        if let Some((to, other)) = landing {
+            edges.push(NavEdge {
+                kind: NavEdgeKind::Fall,
+                to,
+                from_x: edge_x - direction * body.0 / 2.0,
+                to_x: x,
+                cost: body.0 + (other.y - node.y) * 0.5 + FALL_COST,
+            });
+        }
+    }
+
+    edges
+}
+
+/// An edge for jumping from one surface to another, if a guard can make the jump without hitting
+/// anything on the way
+fn jump_edge(
+    nodes: &[NavNode],
+    from: usize,
+    to: usize,
+    solid: &[CollisionGeometry],
+    body: (f32, f32),
+    trajectory: &[Vector2<f32>],
+) -> Option<NavEdge> {
+    let start = nodes[from];
+    let end = nodes[to];
+    let half_width = body.0 / 2.0;
+
+    let rise = start.y - end.y;
+
+    // Take off from the nearest end of the surface and land just inside the other one. Surfaces
+    // which overlap are jumped between around the edge of the higher one, with enough of a run up
+    // to clear it.
+    let (from_x, to_x) = if end.left >= start.right {
+        (start.right - half_width, end.left + half_width)
+    } else if end.right <= start.left {
+        (start.left + half_width, end.right - half_width)
+    } else if rise > 0.0 {
+        let run_up = trajectory
+            .iter()
+            .find(|offset| -offset.y >= rise)
+            .map_or(0.0, |offset| offset.x);
+        let left = end.left - half_width - run_up;
+        let right = end.right + half_width + run_up;
+        if left - half_width >= start.left {
+            (left, end.left + half_width)
+        } else if right + half_width <= start.right {
+            (right, end.right - half_width)
+        } else {
+            return None;
+        }
+    } else {
+        // Dropping down onto a surface below is done by falling
+        return None;
+    };
+
+    let distance = (to_x - from_x).abs();
+    let direction = (to_x - from_x).signum();
+
+    // Follow the jump until it comes back down to the height of the landing surface, steering
+    // towards the landing point and no further
+    let mut apex_passed = false;
+    let mut previous_height = 0.0;
+    for offset in trajectory {
+        let feet = Point2::new(
+            from_x + direction * offset.x.min(distance),
+            start.y + offset.y,
+        );
+        if blocked(&body_at(feet, body), solid) {
+            return None;
+        }
+
+        let height = -offset.y;
+        apex_passed = apex_passed || height < previous_height;
+        previous_height = height;
+
+        if apex_passed && height <= rise {
+            if offset.x < distance {
+                return None;
+            }
+
+            return Some(NavEdge {
+                kind: NavEdgeKind::Jump,
+                to,
+                from_x,
+                to_x,
+                cost: distance + rise.abs() + JUMP_COST,
+            });
+        }
+    }
+
+    None
+}
+
+/// Simulates a guard jumping while walking forwards, returning how far its feet have moved from
+/// the take off point at each step. Positive y is down, like everywhere else.
+fn simulate_jump() -> Vec<Vector2<f32>> {
+    let air_speed = DEFAULT_PATROL_SPEED.min(::GUARD_AIR_PATROL_SPEED);
+
+    // Walking on the ground, friction balances out the walking force at this speed
+    let mut velocity = Vector2::new(
+        DEFAULT_PATROL_SPEED * SIMULATION_STEP / ::GROUND_FRICTION,
+        -::JUMP_FORCE * SIMULATION_STEP,
+    );
+    let mut offset = Vector2::new(0.0, 0.0);
+    let mut trajectory = Vec::new();
+
+    let steps = (MAX_AIR_TIME / SIMULATION_STEP) as usize;
+    for _ in 0..steps {
+        velocity += Vector2::new(air_speed, ::GRAVITY_ACCEL) * SIMULATION_STEP;
+        velocity -= velocity * ::AIR_FRICTION;
+        offset += velocity * SIMULATION_STEP;
+        trajectory.push(offset);
+    }
+
+    trajectory
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: (f32, f32) = (0.9, 1.8);
+
+    fn rect(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> CollisionGeometry {
+        CollisionGeometry::Rect(Aabb2::new(Point2::new(min_x, min_y), Point2::new(max_x, max_y)))
+    }
+
+    fn node_at(nodes: &[NavNode], x: f32, y: f32) -> usize {
+        nodes
+            .iter()
+            .position(|node| node.left <= x && x <= node.right && (node.y - y).abs() < 0.01)
+            .expect("no node there")
+    }
+
+    #[test]
+    fn slopes_block_but_are_not_walked_on() {
+        // A floor with a slope rising to the right sitting on it
+        let solid = vec![
+            rect(0.0, 10.0, 20.0, 11.0),
+            CollisionGeometry::Convex(vec![
+                Point2::new(10.0, 10.0),
+                Point2::new(14.0, 10.0),
+                Point2::new(14.0, 6.0),
+            ]),
+        ];
+        let nodes = surfaces(&solid, &[], BODY);
+
+        assert_eq!(nodes.len(), 2);
+        // The floor is cut where the slope is too low to stand under, not at its bounding box
+        assert!((nodes[0].left - 0.0).abs() < 0.01);
+        assert!((nodes[0].right - 10.05).abs() < 0.01);
+        assert!((nodes[1].left - 14.0).abs() < 0.01);
+        assert!((nodes[1].right - 20.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn platforms_and_headroom() {
+        let solid = vec![
+            rect(0.0, 10.0, 20.0, 11.0),
+            // A low ceiling over the right hand end of the floor
+            rect(15.0, 8.5, 20.0, 9.0),
+            // Too narrow to stand on
+            rect(3.0, 6.0, 3.5, 6.5),
+        ];
+        let one_way = vec![Aabb2::new(Point2::new(6.0, 7.0), Point2::new(9.0, 7.25))];
+        let nodes = surfaces(&solid, &one_way, BODY);
+
+        assert_eq!(nodes.len(), 3);
+        let floor = nodes[node_at(&nodes, 1.0, 10.0)];
+        assert!((floor.right - 15.0).abs() < 0.01);
+        node_at(&nodes, 7.0, 7.0);
+        node_at(&nodes, 17.0, 8.5);
+    }
+
+    #[test]
+    fn jumps_up_onto_low_ledges_only() {
+        let solid = vec![
+            rect(0.0, 10.0, 10.0, 11.0),
+            rect(10.5, 9.0, 14.0, 11.0),
+            rect(14.5, 2.0, 18.0, 11.0),
+        ];
+        let nodes = surfaces(&solid, &[], BODY);
+        let trajectory = simulate_jump();
+        let floor = node_at(&nodes, 5.0, 10.0);
+        let ledge = node_at(&nodes, 12.0, 9.0);
+        let tower = node_at(&nodes, 16.0, 2.0);
+
+        let edge = jump_edge(&nodes, floor, ledge, &solid, BODY, &trajectory).expect("no jump");
+        assert_eq!(edge.kind, NavEdgeKind::Jump);
+        assert_eq!(edge.to, ledge);
+        assert!(jump_edge(&nodes, ledge, tower, &solid, BODY, &trajectory).is_none());
+    }
+
+    #[test]
+    fn jumps_are_blocked_by_shapes_in_the_way() {
+        let overhang = CollisionGeometry::Convex(vec![
+            Point2::new(9.5, 6.0),
+            Point2::new(11.0, 6.0),
+            Point2::new(11.0, 7.0),
+        ]);
+        let mut solid = vec![rect(0.0, 10.0, 10.0, 11.0), rect(10.5, 9.0, 14.0, 11.0)];
+        let nodes = surfaces(&solid, &[], BODY);
+        let trajectory = simulate_jump();
+        let floor = node_at(&nodes, 5.0, 10.0);
+        let ledge = node_at(&nodes, 12.0, 9.0);
+        assert!(jump_edge(&nodes, floor, ledge, &solid, BODY, &trajectory).is_some());
+
+        solid.push(overhang);
+        assert!(jump_edge(&nodes, floor, ledge, &solid, BODY, &trajectory).is_none());
+    }
+
+    #[test]
+    fn paths_up_and_back_down() {
+        let solid = vec![
+            rect(0.0, 10.0, 10.0, 11.0),
+            rect(10.5, 9.0, 14.0, 11.0),
+            rect(30.0, 10.0, 40.0, 11.0),
+        ];
+        let graph = NavGraph::build(&solid, &[], BODY);
+
+        let up = graph
+            .find_path(Point2::new(2.0, 10.0), Point2::new(12.0, 9.0))
+            .expect("no path up");
+        assert_eq!(up.len(), 1);
+        assert_eq!(up[0].kind, NavEdgeKind::Jump);
+
+        let same = graph.find_path(Point2::new(2.0, 10.0), Point2::new(8.0, 9.5));
+        assert_eq!(same.map(|path| path.len()), Some(0));
+
+        // The far floor is too far away to jump to
+        assert!(
+            graph
+                .find_path(Point2::new(2.0, 10.0), Point2::new(35.0, 10.0))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn paths_prefer_falling_to_jumping_down() {
+        let solid = vec![rect(0.0, 10.0, 20.0, 11.0), rect(0.0, 7.0, 5.0, 7.5)];
+        let graph = NavGraph::build(&solid, &[], BODY);
+
+        let down = graph
+            .find_path(Point2::new(2.0, 7.0), Point2::new(15.0, 10.0))
+            .expect("no path down");
+        assert_eq!(down.len(), 1);
+        assert_eq!(down[0].kind, NavEdgeKind::Fall);
+    }
+}
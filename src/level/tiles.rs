@@ -0,0 +1,120 @@
+use cgmath::Point2;
+use collision::{Aabb2, Contains};
+use std::collections::HashSet;
+use tiled::{Map, PropertyValue};
+
+/// A grid with one flag per tile of a map
+pub struct TileGrid {
+    width: usize,
+    height: usize,
+    cells: Vec<bool>,
+}
+
+impl TileGrid {
+    /// Marks every tile on any layer of the map which has the given boolean property set in its
+    /// tileset
+    pub fn from_property(map: &Map, property: &str) -> Self {
+        let marked: HashSet<u32> = map.tilesets
+            .iter()
+            .flat_map(|set| {
+                set.tiles
+                    .iter()
+                    .filter(move |tile| match tile.properties.get(property) {
+                        Some(&PropertyValue::BoolValue(value)) => value,
+                        _ => false,
+                    })
+                    .map(move |tile| set.first_gid + tile.id)
+            })
+            .collect();
+
+        let width = map.width as usize;
+        let height = map.height as usize;
+        let mut cells = vec![false; width * height];
+
+        if !marked.is_empty() {
+            for layer in &map.layers {
+                for (y, row) in layer.tiles.iter().enumerate().take(height) {
+                    for (x, tile) in row.iter().enumerate().take(width) {
+                        if marked.contains(tile) {
+                            cells[y * width + x] = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        TileGrid { width, height, cells }
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.cells[y * self.width + x]
+    }
+
+    /// Unmarks every tile whose centre is inside the given area, which is in tile units
+    pub fn clear(&mut self, area: &Aabb2<f32>) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if area.contains(&Point2::new(x as f32 + 0.5, y as f32 + 0.5)) {
+                    self.cells[y * self.width + x] = false;
+                }
+            }
+        }
+    }
+
+    /// Covers the marked tiles with as few rectangles as it can, in tile units.
+    ///
+    /// This greedily takes the first marked tile, grows it as far right as possible, then as far
+    /// down as the whole row allows, and repeats until every tile is covered.
+    pub fn merged_rects(&self) -> Vec<Aabb2<f32>> {
+        self.merge(true)
+    }
+
+    /// Like `merged_rects`, but never merges tiles on different rows, so that every row keeps its
+    /// own top edge
+    pub fn merged_rows(&self) -> Vec<Aabb2<f32>> {
+        self.merge(false)
+    }
+
+    fn merge(&self, grow_down: bool) -> Vec<Aabb2<f32>> {
+        let mut covered = vec![false; self.cells.len()];
+        let mut rects = Vec::new();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !self.get(x, y) || covered[y * self.width + x] {
+                    continue;
+                }
+
+                let free = |x: usize, y: usize, covered: &[bool]| {
+                    self.get(x, y) && !covered[y * self.width + x]
+                };
+
+                let mut width = 1;
+                while x + width < self.width && free(x + width, y, &covered) {
+                    width += 1;
+                }
+
+                let mut height = 1;
+                while grow_down
+                    && y + height < self.height
+                    && (x..x + width).all(|column| free(column, y + height, &covered))
+                {
+                    height += 1;
+                }
+
+                for covered_y in y..y + height {
+                    for covered_x in x..x + width {
+                        covered[covered_y * self.width + covered_x] = true;
+                    }
+                }
+
+                rects.push(Aabb2::new(
+                    Point2::new(x as f32, y as f32),
+                    Point2::new((x + width) as f32, (y + height) as f32),
+                ));
+            }
+        }
+
+        rects
+    }
+}
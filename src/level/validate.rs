@@ -0,0 +1,391 @@
+use canvas::{IMAGE_SIZE, MAX_COLOURS};
+use cgmath::Point2;
+use collision::Aabb2;
+use image;
+use itertools::Itertools;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use tiled::{self, Map, Object, ObjectShape};
+use super::{is_route_of, string_property, DoorState, GuardKind, Level, GUARD_SPAWN_TYPES};
+
+/// Object types which mean something in the `objects` layer
+const OBJECT_TYPES: &[&str] = &[
+    "guard_spawn",
+    "elite_guard_spawn",
+    "dog_spawn",
+    "security_camera",
+    "guard_pathfind",
+    "turn_around",
+    "jump_left",
+    "jump_right",
+    "exit",
+    "painting",
+    "door",
+    "key",
+    "hiding_spot",
+    "shadow",
+    "ladder",
+    "tripwire",
+];
+/// Object names which mean something in the `objects` layer
+const OBJECT_NAMES: &[&str] = &["easel", "player_spawn"];
+/// Object types which mean something in the `collision` layer
+const COLLISION_TYPES: &[&str] = &["", "clear", "one_way"];
+/// How far a spawned body can start inside level geometry before it's worth warning about
+const SPAWN_OVERLAP_TOLERANCE: f32 = 0.25;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Something wrong with a map, found by `validate`
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Problem {
+    fn error<S: Into<String>>(message: S) -> Self {
+        Problem {
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+
+    fn warning<S: Into<String>>(message: S) -> Self {
+        Problem {
+            severity: Severity::Warning,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+/// Parses the map at the given path and checks it for problems, without needing a window.
+///
+/// `resource_dir` is the directory which tileset images are loaded relative to, like the game's
+/// resource directory.
+pub fn validate_file(path: &Path, resource_dir: &Path) -> Vec<Problem> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return vec![Problem::error(format!("could not open map: {}", e))],
+    };
+
+    match tiled::parse(BufReader::new(file)) {
+        Ok(map) => validate(&map, path, resource_dir),
+        Err(e) => vec![Problem::error(format!("could not parse map: {:?}", e))],
+    }
+}
+
+/// Checks a map for anything which would stop it from loading or playing properly. `file` is the
+/// path the map was read from.
+pub fn validate(map: &Map, file: &Path, resource_dir: &Path) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    check_tilesets(map, resource_dir, &mut problems);
+    check_objects(map, &mut problems);
+
+    // The rest of the checks need the parsed level
+    match Level::from_map(map, file) {
+        Ok(level) => {
+            check_spawns(&level, &mut problems);
+            check_exits(&level, &mut problems);
+            check_paintings(&level, resource_dir, &mut problems);
+            check_doors(&level, &mut problems);
+        }
+        Err(e) => problems.push(Problem::error(e.to_string())),
+    }
+
+    check_unused_paths(map, &mut problems);
+
+    problems
+}
+
+fn check_tilesets(map: &Map, resource_dir: &Path, problems: &mut Vec<Problem>) {
+    for set in &map.tilesets {
+        match set.images.first() {
+            Some(image) => {
+                if !resource_dir.join(&image.source).is_file() {
+                    problems.push(Problem::error(format!(
+                        "tileset `{}` uses image `{}`, which does not exist",
+                        set.name, image.source
+                    )));
+                }
+            }
+            None => problems.push(Problem::error(format!("tileset `{}` has no image", set.name))),
+        }
+    }
+}
+
+fn check_objects(map: &Map, problems: &mut Vec<Problem>) {
+    let map_width = (map.width * map.tile_width) as f32;
+    let map_height = (map.height * map.tile_height) as f32;
+
+    for group in &map.object_groups {
+        for object in &group.objects {
+            match group.name.as_str() {
+                "objects" => {
+                    let known = OBJECT_TYPES.contains(&object.obj_type.as_str())
+                        || (object.obj_type.is_empty() && OBJECT_NAMES.contains(&object.name.as_str()));
+                    if !known && object.obj_type.is_empty() {
+                        problems.push(Problem::warning(format!(
+                            "{} has no type and is ignored",
+                            describe(object)
+                        )));
+                    } else if !known {
+                        problems.push(Problem::warning(format!(
+                            "{} has unknown type `{}` and is ignored",
+                            describe(object),
+                            object.obj_type
+                        )));
+                    }
+
+                    check_guard_properties(object, problems);
+                }
+                "collision" => {
+                    if !COLLISION_TYPES.contains(&object.obj_type.as_str()) {
+                        problems.push(Problem::warning(format!(
+                            "{} in the collision layer has unknown type `{}`",
+                            describe(object),
+                            object.obj_type
+                        )));
+                    }
+
+                    if let ObjectShape::Rect { width, height } = object.shape {
+                        if width <= 0.0 || height <= 0.0 {
+                            problems.push(Problem::error(format!(
+                                "{} is a collision rect with no area",
+                                describe(object)
+                            )));
+                        }
+                    }
+                }
+                _ => (),
+            }
+
+            let bounds = object_bounds(object);
+            let inside = bounds.min.x >= 0.0 && bounds.min.y >= 0.0 && bounds.max.x <= map_width
+                && bounds.max.y <= map_height;
+            let outside = bounds.max.x < 0.0 || bounds.max.y < 0.0 || bounds.min.x > map_width
+                || bounds.min.y > map_height;
+
+            if outside {
+                problems.push(Problem::error(format!(
+                    "{} is entirely outside of the map",
+                    describe(object)
+                )));
+            } else if !inside {
+                problems.push(Problem::warning(format!(
+                    "{} is partly outside of the map",
+                    describe(object)
+                )));
+            }
+        }
+    }
+}
+
+fn check_spawns(level: &Level, problems: &mut Vec<Problem>) {
+    let mut spawns = vec![("player spawn".to_owned(), level.player_spawn, ::PLAYER_SIZE)];
+    spawns.extend(
+        level
+            .guards
+            .iter()
+            .enumerate()
+            // Cameras don't have a body, so they're usually mounted inside walls
+            .filter(|&(_i, guard)| guard.kind != GuardKind::Camera)
+            .map(|(i, guard)| (format!("guard spawn {}", i + 1), guard.spawn, ::GUARD_SIZE)),
+    );
+
+    for (name, spawn, size) in spawns {
+        let body = Aabb2::new(
+            Point2::new(
+                spawn.x - size.0 / 2.0 + SPAWN_OVERLAP_TOLERANCE,
+                spawn.y - size.1 / 2.0 + SPAWN_OVERLAP_TOLERANCE,
+            ),
+            Point2::new(
+                spawn.x + size.0 / 2.0 - SPAWN_OVERLAP_TOLERANCE,
+                spawn.y + size.1 / 2.0 - SPAWN_OVERLAP_TOLERANCE,
+            ),
+        );
+
+        for geometry in &level.collision {
+            if geometry.contains(spawn) {
+                problems.push(Problem::error(format!(
+                    "{} at ({}, {}) is inside level geometry",
+                    name, spawn.x, spawn.y
+                )));
+                break;
+            } else if geometry.intersects(&body) {
+                problems.push(Problem::warning(format!(
+                    "{} at ({}, {}) overlaps level geometry and will be pushed out of it",
+                    name, spawn.x, spawn.y
+                )));
+                break;
+            }
+        }
+    }
+}
+
+/// Warns about guard spawns with a `guard_type` or `facing` which isn't understood, since they
+/// would quietly fall back to a standard guard facing left
+fn check_guard_properties(object: &Object, problems: &mut Vec<Problem>) {
+    let spawn_type = GUARD_SPAWN_TYPES
+        .iter()
+        .find(|&&(object_type, _kind)| object_type == object.obj_type);
+    let kind = match spawn_type {
+        Some(&(_object_type, kind)) => kind,
+        None => return,
+    };
+
+    // Only plain guard spawns take their kind from the `guard_type` property
+    if kind.is_none() {
+        if let Some(guard_type) = string_property(object, "guard_type") {
+            if GuardKind::from_name(guard_type).is_none() {
+                problems.push(Problem::warning(format!(
+                    "{} has unknown `guard_type` `{}`, so it spawns a standard guard",
+                    describe(object),
+                    guard_type
+                )));
+            }
+        }
+    }
+
+    if let Some(facing) = string_property(object, "facing") {
+        if facing != "left" && facing != "right" {
+            problems.push(Problem::warning(format!(
+                "{} has unknown `facing` `{}`, so it faces left",
+                describe(object),
+                facing
+            )));
+        }
+    }
+}
+
+fn check_exits(level: &Level, problems: &mut Vec<Problem>) {
+    if level.exits.is_empty() {
+        problems.push(Problem::warning(
+            "the map has no `exit` objects, so the level can't be finished",
+        ));
+    }
+}
+
+fn check_paintings(level: &Level, resource_dir: &Path, problems: &mut Vec<Problem>) {
+    if level.paintings.is_empty() {
+        problems.push(Problem::warning(
+            "the map has no `painting` objects, so there is nothing to steal",
+        ));
+    }
+
+    for painting in &level.paintings {
+        let path = resource_dir.join("paintings").join(&painting.painting);
+        if !path.is_file() {
+            problems.push(Problem::error(format!(
+                "painting `{}` does not exist in the paintings directory",
+                painting.painting
+            )));
+            continue;
+        }
+
+        // The easel can't load paintings of the wrong size or with too many colours
+        let image = match image::open(&path) {
+            Ok(image) => image.to_rgb(),
+            Err(e) => {
+                problems.push(Problem::error(format!(
+                    "painting `{}` could not be opened: {}",
+                    painting.painting, e
+                )));
+                continue;
+            }
+        };
+
+        let (width, height) = image.dimensions();
+        let size = u32::from(IMAGE_SIZE);
+        if width != size || height != size {
+            problems.push(Problem::error(format!(
+                "painting `{}` is {}x{}, but paintings have to be {}x{}",
+                painting.painting, width, height, size, size
+            )));
+        }
+
+        let colours = image.pixels().unique().count();
+        if colours > MAX_COLOURS {
+            problems.push(Problem::error(format!(
+                "painting `{}` has {} colours, but paintings can have at most {}",
+                painting.painting, colours, MAX_COLOURS
+            )));
+        }
+    }
+}
+
+fn check_doors(level: &Level, problems: &mut Vec<Problem>) {
+    for (i, door) in level.doors.iter().enumerate() {
+        match door.key {
+            Some(ref key) => {
+                if !level.keys.iter().any(|other| other.key == *key) {
+                    problems.push(Problem::warning(format!(
+                        "door {} needs key `{}`, but there is no key with that name",
+                        i + 1,
+                        key
+                    )));
+                }
+            }
+            None if door.state == DoorState::Locked => {
+                problems.push(Problem::warning(format!(
+                    "door {} is locked but has no `key` property, so it can never be opened",
+                    i + 1
+                )));
+            }
+            None => (),
+        }
+    }
+}
+
+fn check_unused_paths(map: &Map, problems: &mut Vec<Problem>) {
+    let spawns = Level::find_guard_spawns(map);
+    for path in Level::find_objects_by_type(map, "objects", "guard_pathfind") {
+        if !spawns.iter().any(|&(spawn, _kind)| is_route_of(path, spawn)) {
+            problems.push(Problem::warning(format!(
+                "{} is not linked to any guard",
+                describe(path)
+            )));
+        }
+    }
+}
+
+fn object_bounds(object: &Object) -> Aabb2<f32> {
+    let origin = Point2::new(object.x, object.y);
+    match object.shape {
+        ObjectShape::Rect { width, height } | ObjectShape::Ellipse { width, height } => {
+            Aabb2::new(origin, Point2::new(object.x + width, object.y + height))
+        }
+        ObjectShape::Polyline { ref points } | ObjectShape::Polygon { ref points } => {
+            points.iter().fold(Aabb2::new(origin, origin), |bounds, &(x, y)| {
+                Aabb2::new(
+                    Point2::new(bounds.min.x.min(object.x + x), bounds.min.y.min(object.y + y)),
+                    Point2::new(bounds.max.x.max(object.x + x), bounds.max.y.max(object.y + y)),
+                )
+            })
+        }
+    }
+}
+
+fn describe(object: &Object) -> String {
+    if object.name.is_empty() {
+        format!("object {}", object.id)
+    } else {
+        format!("object {} (`{}`)", object.id, object.name)
+    }
+}
@@ -0,0 +1,42 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use super::Level;
+
+/// Keeps track of when the files a level was built from were last modified, so that the level can
+/// be reloaded when they are edited
+pub struct LevelWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl LevelWatcher {
+    /// Watches the map file and tileset images of the given level
+    pub fn new(level: &Level) -> Self {
+        let files = Some(&level.file)
+            .into_iter()
+            .chain(level.tileset_images.iter())
+            .map(|path| (path.clone(), modified(path)))
+            .collect();
+
+        LevelWatcher { files }
+    }
+
+    /// Whether any of the files have been modified since the watcher was created or since this
+    /// last returned true
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for &mut (ref path, ref mut last_modified) in self.files.iter_mut() {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
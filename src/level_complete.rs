@@ -0,0 +1,68 @@
+use entity::resources::LevelProgress;
+use ggez::{self, GameResult, graphics};
+use gui;
+
+/// Shown when the player escapes from a level, with how well they did
+pub struct LevelCompleteGui {
+    lines: Vec<String>,
+    size: (f32, f32),
+    clicked: bool,
+}
+
+impl LevelCompleteGui {
+    /// `last_level` is whether there are no more levels after this one
+    pub fn new(progress: &LevelProgress, last_level: bool) -> Self {
+        let seconds = progress.time as u32;
+        let accuracy = progress.accuracy.unwrap_or(0.0) * 100.0;
+
+        let mut lines = vec![
+            "Level complete!".to_owned(),
+            String::new(),
+            format!("Time: {}:{:02}", seconds / 60, seconds % 60),
+            format!("Replica accuracy: {:.0}%", accuracy),
+            format!("Times spotted: {}", progress.times_spotted),
+            String::new(),
+        ];
+
+        if last_level {
+            lines.push("That was the last painting! Click to play again".to_owned());
+        } else {
+            lines.push("Click to continue".to_owned());
+        }
+
+        LevelCompleteGui {
+            lines,
+            size: (0.0, 0.0),
+            clicked: false,
+        }
+    }
+}
+
+impl gui::Gui for LevelCompleteGui {
+    fn resize(&mut self, width: f32, height: f32) {
+        self.size = (width, height);
+    }
+
+    fn update(&mut self, _mouse_x: f32, _mouse_y: f32) -> GameResult<()> {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut ggez::Context, font: &graphics::Font, _mouse_x: f32, _mouse_y: f32) -> GameResult<()> {
+        gui::draw_centred_lines(ctx, font, self.size, &self.lines)
+    }
+
+    fn mouse_pressed(&mut self, _mouse_x: f32, _mouse_y: f32) {}
+
+    fn mouse_released(&mut self, _mouse_x: f32, _mouse_y: f32) {
+        self.clicked = true;
+    }
+
+    fn poll_outcome(&mut self) -> Option<gui::GuiOutcome> {
+        if self.clicked {
+            self.clicked = false;
+            Some(gui::GuiOutcome::NextLevel)
+        } else {
+            None
+        }
+    }
+}
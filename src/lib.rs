@@ -0,0 +1,53 @@
+#![feature(nll)]
+#![feature(specialization)]
+#![feature(match_default_bindings)]
+#![feature(custom_attribute)]
+#![allow(unused_attributes)]
+
+extern crate cgmath;
+extern crate collision;
+extern crate ggez;
+extern crate image;
+extern crate itertools;
+extern crate ord_subset;
+extern crate rand;
+extern crate rhusics_core;
+extern crate rhusics_ecs;
+extern crate shrev;
+extern crate specs;
+extern crate tiled;
+
+pub mod camera;
+pub mod canvas;
+pub mod capture;
+pub mod entity;
+pub mod gui;
+pub mod level;
+pub mod level_complete;
+pub mod load_error;
+pub mod util;
+pub mod viewport;
+
+/// The size of the window when the game starts
+pub const WINDOW_SIZE: (u32, u32) = (800, 600);
+/// The smallest area, in level pixels, which is always visible. The window is scaled up by the
+/// largest whole number which still fits this in.
+pub const MIN_LOGICAL_SIZE: (u32, u32) = (400, 300);
+
+pub const PLAYER_SIZE: (f32, f32) = (0.9, 1.8);
+/// The size of the player while crouching, small enough to fit through one tile high gaps
+pub const PLAYER_CROUCH_SIZE: (f32, f32) = (0.9, 0.9);
+pub const GUARD_SIZE: (f32, f32) = (0.9, 1.8);
+
+/// The acceleration of gravity, in tiles per second squared
+pub const GRAVITY_ACCEL: f32 = 30.0;
+/// The upwards force applied for a single frame to jump
+pub const JUMP_FORCE: f32 = 1000.0;
+/// How much of a body's velocity friction takes away each frame while it's on the ground
+pub const GROUND_FRICTION: f32 = 0.1;
+/// How much of a body's velocity drag takes away each frame while it's in the air
+pub const AIR_FRICTION: f32 = 0.02;
+/// The most walking force a patrolling guard can apply while in the air
+pub const GUARD_AIR_PATROL_SPEED: f32 = 5.0;
+/// The most walking force a chasing guard can apply while in the air
+pub const GUARD_AIR_CHASE_SPEED: f32 = 10.0;
@@ -0,0 +1,40 @@
+use ggez::{self, GameResult, graphics};
+use gui;
+
+const LOAD_ERROR_MESSAGE: &'static str = "No level could be loaded!";
+
+/// Shown instead of a level when none of the levels could be loaded
+pub struct LoadErrorGui {
+    errors: Vec<String>,
+    size: (f32, f32),
+}
+
+impl LoadErrorGui {
+    pub fn new(errors: Vec<String>) -> Self {
+        LoadErrorGui {
+            errors,
+            size: (0.0, 0.0),
+        }
+    }
+}
+
+impl gui::Gui for LoadErrorGui {
+    fn resize(&mut self, width: f32, height: f32) {
+        self.size = (width, height);
+    }
+
+    fn update(&mut self, _mouse_x: f32, _mouse_y: f32) -> GameResult<()> {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut ggez::Context, font: &graphics::Font, _mouse_x: f32, _mouse_y: f32) -> GameResult<()> {
+        let mut lines = vec![LOAD_ERROR_MESSAGE.to_owned()];
+        lines.extend(self.errors.iter().cloned());
+
+        gui::draw_centred_lines(ctx, font, self.size, &lines)
+    }
+
+    fn mouse_pressed(&mut self, _mouse_x: f32, _mouse_y: f32) {}
+
+    fn mouse_released(&mut self, _mouse_x: f32, _mouse_y: f32) {}
+}
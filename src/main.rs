@@ -1,34 +1,25 @@
 #![feature(nll)]
-#![feature(specialization)]
 #![feature(match_default_bindings)]
-#![feature(custom_attribute)]
-#![allow(unused_attributes)]
 
 extern crate cgmath;
 extern crate collision;
 extern crate ggez;
 extern crate image;
-extern crate itertools;
-extern crate ord_subset;
 extern crate rand;
 extern crate rhusics_core;
 extern crate rhusics_ecs;
 extern crate shrev;
 extern crate specs;
-extern crate tiled;
+extern crate toam;
 
-use canvas::PaintingCanvas;
 use cgmath::{Basis2, One, Point2, Vector2};
-use collision::Contains;
-use entity::components::*;
-use entity::resources::*;
-use entity::systems::*;
+use collision::{Aabb2, Contains, Discrete};
 use ggez::{Context, ContextBuilder, GameResult};
 use ggez::conf::{WindowMode, WindowSetup};
 use ggez::event::{self, EventHandler, Keycode, Mod};
 use ggez::graphics::{self, FilterMode};
-use level::Level;
-use rhusics_core::{Pose, RigidBody};
+use image::RgbaImage;
+use rhusics_core::{NextFrame, Pose, RigidBody};
 use rhusics_core::ContactEvent;
 use rhusics_ecs::{DeltaTime, WithRigidBody};
 use rhusics_ecs::collide2d::*;
@@ -36,70 +27,161 @@ use rhusics_ecs::physics2d::{ContactResolutionSystem2, CurrentFrameUpdateSystem2
                              Mass2, NextFrameSetupSystem2, register_physics,
                              SpatialCollisionSystem2, SpatialSortingSystem2, Velocity2};
 use shrev::EventChannel;
-use specs::{Dispatcher, DispatcherBuilder, Entity, RunNow, World};
-use std::fs::File;
+use specs::{Dispatcher, DispatcherBuilder, Entity, Join, RunNow, World};
 use std::path::Path;
+use toam::{capture, entity, gui};
+use toam::{GUARD_SIZE, PLAYER_SIZE, WINDOW_SIZE};
+use toam::camera::Camera;
+use toam::canvas::PaintingCanvas;
+use toam::entity::components::*;
+use toam::entity::distraction::{self, DISTRACTION_SIZE};
+use toam::entity::noise::NoiseEvent;
+use toam::entity::resources::*;
+use toam::entity::systems::*;
+use toam::gui::GuiOutcome;
+use toam::level::{CollisionGeometry, DoorState, GuardKind, Level, LevelWatcher};
+use toam::level_complete::LevelCompleteGui;
+use toam::load_error::LoadErrorGui;
+use toam::viewport::Viewport;
 
-mod canvas;
-mod capture;
-mod entity;
-mod gui;
-mod level;
-mod util;
-
-// TODO gegy fight me, doesnt fit on my screen
-// TODO dynamically scale this
-const SCREEN_SIZE: (u32, u32) = (800, 600);
-
-const GLOBAL_SCALE: f32 = 2.0;
-const SCALED_SIZE: (f32, f32) = (
-    SCREEN_SIZE.0 as f32 / GLOBAL_SCALE,
-    SCREEN_SIZE.1 as f32 / GLOBAL_SCALE,
-);
-
-const PLAYER_SIZE: (f32, f32) = (0.9, 1.8);
-const GUARD_SIZE: (f32, f32) = (0.9, 1.8);
+/// The maps played through in the game, in order
+const LEVELS: &[&str] = &[
+    "resources/levels/level_1.tmx",
+    "resources/levels/level_2.tmx",
+    "resources/levels/level_3.tmx",
+];
+/// How often the level's files are checked for changes in debug mode, in seconds
+const RELOAD_POLL_INTERVAL: f32 = 0.5;
+/// How many distractions the player has to throw at the start of each level
+const STARTING_DISTRACTIONS: u32 = 3;
+/// How heavy a thrown distraction is, compared to the player
+const DISTRACTION_MASS: f32 = 0.1;
+/// How far above horizontal the player aims their first throw, in radians
+const DEFAULT_AIM_ANGLE: f32 = 0.6;
+/// How close the player has to be to a door to open or shut it, in tiles
+const PLAYER_DOOR_REACH: f32 = 0.5;
+/// How far the player can be inside of reloaded level geometry before they're moved back to the
+/// spawn
+const RELOAD_OVERLAP_TOLERANCE: f32 = 0.25;
 
 struct GameState<'a> {
-    level_state: LevelState<'a>,
+    level_state: Option<LevelState<'a>>,
+    /// The index in `LEVELS` of the level being played
+    level_index: usize,
     render_state: RenderState,
     font: graphics::Font,
 }
 
 impl<'a> GameState<'a> {
     fn new(ctx: &mut Context) -> GameResult<GameState<'a>> {
-        // TODO: Terrible code but works!
-        let mut levels = vec![Level::load_from(
-            File::open(&Path::new("resources/levels/level_1.tmx"))
-                .expect("Failed to locate map file"),
-            ctx,
-        )?, Level::load_from(
-            File::open(&Path::new("resources/levels/level_2.tmx"))
-                .expect("Failed to locate map file"),
-            ctx,
-        )?];
-
         let font = graphics::Font::new(ctx, "/arial.ttf", 16)?;
 
-        Ok(GameState {
-            level_state: LevelState::new(ctx, levels.remove(0))?,
+        let mut state = GameState {
+            level_state: None,
+            level_index: 0,
             render_state: RenderState::new(),
             font,
-        })
+        };
+        state.resize(ctx, WINDOW_SIZE.0, WINDOW_SIZE.1)?;
+        state.load_level(ctx, 0)?;
+
+        Ok(state)
+    }
+
+    /// Starts the first level from the given index onwards which loads successfully. Levels which
+    /// fail to load are skipped, and if none of them load an error screen is shown instead.
+    fn load_level(&mut self, ctx: &mut Context, first: usize) -> GameResult<()> {
+        let mut errors = Vec::new();
+
+        for (index, path) in LEVELS.iter().enumerate().skip(first) {
+            match Level::load(Path::new(path), ctx) {
+                Ok(level) => {
+                    let logical_size = self.render_state.viewport.logical_size;
+                    let last_level = index + 1 == LEVELS.len();
+                    self.level_state = Some(LevelState::new(ctx, level, logical_size, last_level)?);
+                    self.level_index = index;
+                    return Ok(());
+                }
+                Err(e) => {
+                    eprintln!("Skipping level: {}", e);
+                    errors.push(e.to_string());
+                }
+            }
+        }
+
+        self.level_state = None;
+        self.render_state.show_gui(Box::new(LoadErrorGui::new(errors)));
+
+        Ok(())
+    }
+
+    /// Recomputes the scaling for a window of the given size, and lays everything out again for
+    /// the new logical size
+    fn resize(&mut self, ctx: &mut Context, width: u32, height: u32) -> GameResult<()> {
+        let viewport = Viewport::new(width, height);
+        viewport.apply(ctx)?;
+        self.render_state.viewport = viewport;
+
+        let logical_size = viewport.logical_size;
+        if let Some(ref mut level_state) = self.level_state {
+            level_state.resize(logical_size);
+        }
+        self.render_state.gui.as_mut().map(|gui| {
+            gui.resize(logical_size.0, logical_size.1);
+        });
+
+        Ok(())
+    }
+
+    fn handle_gui_outcome(&mut self, ctx: &mut Context, outcome: GuiOutcome) -> GameResult<()> {
+        match outcome {
+            GuiOutcome::PaintingFinished { accuracy, replica } => {
+                self.render_state.gui = None;
+                if let Some(ref mut level_state) = self.level_state {
+                    level_state.painting_finished(ctx, accuracy, replica)?;
+                }
+            }
+            GuiOutcome::NextLevel => {
+                self.render_state.gui = None;
+                // Start again from the beginning after the last level
+                let next = (self.level_index + 1) % LEVELS.len();
+                self.load_level(ctx, next)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn toggle_fullscreen(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.render_state.fullscreen = !self.render_state.fullscreen;
+        graphics::set_fullscreen(ctx, self.render_state.fullscreen)?;
+
+        let (width, height) = graphics::get_size(ctx);
+        self.resize(ctx, width, height)
     }
 }
 
 impl<'a> EventHandler for GameState<'a> {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        self.level_state.update(ctx, &mut self.render_state);
+        if let Some(ref mut level_state) = self.level_state {
+            level_state.update(ctx, &mut self.render_state);
+        }
 
+        let viewport = self.render_state.viewport;
         self.render_state.gui.as_mut().map(|gui| {
             let mouse_pos = ggez::mouse::get_position(ctx).unwrap();
-            let mouse_x = mouse_pos.x / ::GLOBAL_SCALE;
-            let mouse_y = mouse_pos.y / ::GLOBAL_SCALE;
+            let (mouse_x, mouse_y) = viewport.to_logical(mouse_pos.x, mouse_pos.y);
             gui.update(mouse_x, mouse_y).expect("Failed to update gui!");
         });
 
+        let outcome = self.render_state
+            .gui
+            .as_mut()
+            .and_then(|gui| gui.poll_outcome());
+        if let Some(outcome) = outcome {
+            self.handle_gui_outcome(ctx, outcome)?;
+        }
+
         Ok(())
     }
 
@@ -108,18 +190,22 @@ impl<'a> EventHandler for GameState<'a> {
 
         graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
 
-        self.level_state.render(ctx, &mut self.render_state);
+        if let Some(ref mut level_state) = self.level_state {
+            level_state.render(ctx, &mut self.render_state);
+        }
 
         // FIXME
         let font = self.font.clone();
+        let viewport = self.render_state.viewport;
         self.render_state.gui.as_mut().map(|gui| {
             let mouse_pos = ggez::mouse::get_position(ctx).unwrap();
-            let mouse_x = mouse_pos.x / ::GLOBAL_SCALE;
-            let mouse_y = mouse_pos.y / ::GLOBAL_SCALE;
+            let (mouse_x, mouse_y) = viewport.to_logical(mouse_pos.x, mouse_pos.y);
             gui.draw(ctx, &font, mouse_x, mouse_y)
                 .expect("Failed to draw gui!");
         });
 
+        viewport.draw_letterbox(ctx)?;
+
         graphics::present(ctx);
         Ok(())
     }
@@ -132,8 +218,9 @@ impl<'a> EventHandler for GameState<'a> {
         y: i32,
     ) {
         if button == ggez::event::MouseButton::Left {
+            let (x, y) = self.render_state.viewport.to_logical(x as f32, y as f32);
             self.render_state.gui.as_mut().map(|gui| {
-                gui.mouse_pressed(x as f32 / ::GLOBAL_SCALE, y as f32 / ::GLOBAL_SCALE);
+                gui.mouse_pressed(x, y);
             });
         }
     }
@@ -146,20 +233,27 @@ impl<'a> EventHandler for GameState<'a> {
         y: i32,
     ) {
         if button == ggez::event::MouseButton::Left {
+            let (x, y) = self.render_state.viewport.to_logical(x as f32, y as f32);
             self.render_state.gui.as_mut().map(|gui| {
-                gui.mouse_released(x as f32 / ::GLOBAL_SCALE, y as f32 / ::GLOBAL_SCALE);
+                gui.mouse_released(x, y);
             });
         }
     }
 
+    fn resize_event(&mut self, ctx: &mut Context, width: u32, height: u32) {
+        self.resize(ctx, width, height).expect("Failed to resize!");
+    }
+
     fn key_down_event(&mut self, ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
         use Keycode::*;
         match keycode {
             Escape => ctx.quit().expect("Failed to quit"),
+            F11 => self.toggle_fullscreen(ctx).expect("Failed to toggle fullscreen!"),
             F12 => self.render_state.debug = !self.render_state.debug,
             _ => {
-                self.level_state.key_pressed(ctx, &mut self.render_state, keycode);
-                ()
+                if let Some(ref mut level_state) = self.level_state {
+                    level_state.key_pressed(ctx, &mut self.render_state, keycode);
+                }
             }
         }
     }
@@ -167,8 +261,9 @@ impl<'a> EventHandler for GameState<'a> {
     fn key_up_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
         match keycode {
             _ => {
-                self.level_state.key_released(keycode);
-                ()
+                if let Some(ref mut level_state) = self.level_state {
+                    level_state.key_released(keycode);
+                }
             }
         }
     }
@@ -177,6 +272,8 @@ impl<'a> EventHandler for GameState<'a> {
 struct RenderState {
     debug: bool,
     gui: Option<Box<gui::Gui>>,
+    viewport: Viewport,
+    fullscreen: bool,
 }
 
 impl RenderState {
@@ -184,8 +281,17 @@ impl RenderState {
         RenderState {
             debug: false,
             gui: None,
+            viewport: Viewport::new(WINDOW_SIZE.0, WINDOW_SIZE.1),
+            fullscreen: false,
         }
     }
+
+    /// Lays the gui out for the current screen size and shows it
+    fn show_gui(&mut self, mut gui: Box<gui::Gui>) {
+        let (width, height) = self.viewport.logical_size;
+        gui.resize(width, height);
+        self.gui = Some(gui);
+    }
 }
 
 struct LevelState<'a> {
@@ -193,10 +299,22 @@ struct LevelState<'a> {
     level: Level,
     update_dispatcher: Dispatcher<'a, 'a>,
     locked: bool,
+    /// Whether there are no more levels after this one
+    last_level: bool,
+    player: Entity,
+    /// The entities holding the static collision of the level
+    static_bodies: Vec<Entity>,
+    watcher: LevelWatcher,
+    reload_timer: f32,
 }
 
 impl<'a> LevelState<'a> {
-    fn new(ctx: &mut Context, level: Level) -> GameResult<Self> {
+    fn new(
+        ctx: &mut Context,
+        level: Level,
+        logical_size: (f32, f32),
+        last_level: bool,
+    ) -> GameResult<Self> {
         let player_image = graphics::Image::new(ctx, "/player_right.png")?;
         let guard_image = graphics::Image::new(ctx, "/guard.png")?;
 
@@ -213,11 +331,20 @@ impl<'a> LevelState<'a> {
             .write_resource::<EventChannel<ContactEvent<Entity, Point2<f32>>>>()
             .register_reader();
 
+        world.res.add(EventChannel::<NoiseEvent>::new());
+        let noise_reader = world
+            .write_resource::<EventChannel<NoiseEvent>>()
+            .register_reader();
+
         world.res.add(GlobalPlayerState {
             pos: Point2::new(0.0, 0.0),
             bounds: collision::Aabb2::new(Point2::new(0.0, 0.0), Point2::new(0.0, 0.0)),
             id: 0,
             captured: false,
+            facing: Direction::Right,
+            hiding: None,
+            velocity: Vector2::new(0.0, 0.0),
+            stance: Stance::Standing,
         });
         world.res.add(GameInput::new());
         world
@@ -226,11 +353,78 @@ impl<'a> LevelState<'a> {
         world
             .res
             .add(GuardTurnAroundBoxes(level.guard_turn_around.clone()));
-
         world
+            .res
+            .add(OneWayPlatforms(level.one_way_platforms.clone()));
+        world.res.add(Exits(level.exits.clone()));
+        world.res.add(HidingSpots(level.hiding_spots.clone()));
+        world.res.add(Shadows(level.shadows.clone()));
+        world.res.add(Ladders(level.ladders.clone()));
+        world.res.add(Tripwires(
+            level
+                .tripwires
+                .iter()
+                .map(|&bounds| Tripwire {
+                    bounds,
+                    tripped: false,
+                })
+                .collect(),
+        ));
+        world.res.add(level.nav.clone());
+        world.res.add(LevelProgress::new());
+        world.res.add(Alarm::new());
+        world.res.add(Doors(
+            level
+                .doors
+                .iter()
+                .map(|info| Door {
+                    info: info.clone(),
+                    state: info.state,
+                    sealed: false,
+                    body: None,
+                })
+                .collect(),
+        ));
+        world.res.add(Keys(
+            level
+                .keys
+                .iter()
+                .map(|info| KeyPickup {
+                    info: info.clone(),
+                    collected: false,
+                })
+                .collect(),
+        ));
+        world.res.add(Inventory {
+            keys: Vec::new(),
+            distractions: STARTING_DISTRACTIONS,
+        });
+        world.res.add(Aim {
+            angle: DEFAULT_AIM_ANGLE,
+        });
+        world.res.add(Paintings(
+            level
+                .paintings
+                .iter()
+                .zip(level.painting_images.iter())
+                .map(|(info, image)| PaintingState {
+                    info: info.clone(),
+                    original: image.clone(),
+                    replica: None,
+                    accuracy: None,
+                    status: PaintingStatus::Hanging,
+                })
+                .collect(),
+        ));
+
+        let mut camera = Camera::new(level.size, logical_size);
+        camera.look_at(level.player_spawn);
+        world.res.add(camera);
+
+        let player = world
             .create_entity()
             .with(Player)
-            .with(CollisionState { ground: false, jump_cooldown: 0.0 })
+            .with(CollisionState::new(PLAYER_SIZE))
             .with_dynamic_rigid_body(
                 CollisionShape2::<f32, BodyPose2<f32>, ()>::new_simple(
                     CollisionStrategy::FullResolution,
@@ -246,17 +440,33 @@ impl<'a> LevelState<'a> {
             .build();
 
         for guard_info in level.guards.iter() {
+            // Cameras are fixed in place, so they only need somewhere to be
+            if guard_info.kind == GuardKind::Camera {
+                world
+                    .create_entity()
+                    .with(Directional {
+                        direction: guard_info.facing,
+                    })
+                    .with(GuardAi::new(guard_info.clone()))
+                    .with(BodyPose2::new(guard_info.spawn, Basis2::one()))
+                    .build();
+                continue;
+            }
+
+            let mut sprite = Sprite::new(guard_image.clone());
+            sprite.color = match guard_info.kind {
+                GuardKind::Elite => Some(graphics::Color::new(0.6, 0.65, 1.0, 1.0)),
+                GuardKind::Dog => Some(graphics::Color::new(0.8, 0.6, 0.4, 1.0)),
+                GuardKind::Standard | GuardKind::Camera => None,
+            };
+
             world
                 .create_entity()
-                .with(CollisionState { ground: false, jump_cooldown: 0.0 })
+                .with(CollisionState::new(GUARD_SIZE))
                 .with(Directional {
-                    direction: Direction::Left,
-                })
-                .with(GuardAi {
-                    state: GuardAiState::Patrolling,
-                    info: guard_info.clone(),
-                    turn_around_cooldown: 0.0,
+                    direction: guard_info.facing,
                 })
+                .with(GuardAi::new(guard_info.clone()))
                 .with_dynamic_rigid_body(
                     CollisionShape2::<f32, BodyPose2<f32>, ()>::new_simple(
                         CollisionStrategy::FullResolution,
@@ -268,33 +478,11 @@ impl<'a> LevelState<'a> {
                     RigidBody::default(),
                     Mass2::new(1.0),
                 )
-                .with(Sprite::new(guard_image.clone()))
+                .with(sprite)
                 .build();
         }
 
-        for collision_rect in &level.collision_rects {
-            let Vector2 { x: w, y: h, .. } = collision_rect.max - collision_rect.min;
-
-            world
-                .create_entity()
-                .with_static_rigid_body(
-                    CollisionShape2::<f32, BodyPose2<f32>, ()>::new_simple(
-                        CollisionStrategy::FullResolution,
-                        CollisionMode::Discrete,
-                        Rectangle::new(w, h).into(),
-                    ),
-                    BodyPose2::new(
-                        Point2::new(
-                            collision_rect.min.x + w / 2.0,
-                            collision_rect.min.y + h / 2.0,
-                        ),
-                        Basis2::one(),
-                    ),
-                    RigidBody::default(),
-                    Mass2::new(1.0),
-                )
-                .build();
-        }
+        let static_bodies = LevelState::create_static_bodies(&mut world, &level);
 
         let update_dispatcher = DispatcherBuilder::new()
             .add(
@@ -305,8 +493,23 @@ impl<'a> LevelState<'a> {
                 &[],
             )
             .add(PlayerSystem, "player", &["collision_state"])
-            .add(PhysicsExtras, "gravity", &["collision_state"])
-            .add(GuardAiSystem, "guard_ai", &["collision_state"])
+            .add(PhysicsExtras, "gravity", &["collision_state", "player"])
+            .add(PlayerNoiseSystem::new(), "player_noise", &["collision_state"])
+            .add(SecurityCameraSystem, "security_cameras", &["player"])
+            .add(TripwireSystem, "tripwires", &["player"])
+            .add(DistractionSystem, "distractions", &["collision_state"])
+            .add(
+                GuardAiSystem::new(noise_reader),
+                "guard_ai",
+                &[
+                    "collision_state",
+                    "player_noise",
+                    "security_cameras",
+                    "tripwires",
+                    "distractions",
+                ],
+            )
+            .add(AlarmSystem, "alarm", &["guard_ai"])
             .add(
                 CurrentFrameUpdateSystem2::<f32, BodyPose2<f32>>::new(),
                 "solver",
@@ -334,9 +537,252 @@ impl<'a> LevelState<'a> {
                 "resolution",
                 &["collision"],
             )
+            .add(OneWayPlatformSystem, "one_way_platforms", &["resolution"])
+            .add(CameraSystem, "camera", &["player"])
+            .add(LevelProgressSystem, "level_progress", &["player"])
+            .add(KeyPickupSystem, "key_pickup", &["player"])
             .build();
 
-        Ok(LevelState { world, level, update_dispatcher, locked: false })
+        let watcher = LevelWatcher::new(&level);
+
+        let mut level_state = LevelState {
+            world,
+            level,
+            update_dispatcher,
+            locked: false,
+            last_level,
+            player,
+            static_bodies,
+            watcher,
+            reload_timer: RELOAD_POLL_INTERVAL,
+        };
+        level_state.sync_doors();
+
+        Ok(level_state)
+    }
+
+    fn create_static_bodies(world: &mut World, level: &Level) -> Vec<Entity> {
+        level
+            .collision
+            .iter()
+            .map(|geometry| LevelState::create_static_body(world, geometry))
+            .collect()
+    }
+
+    fn create_static_body(world: &mut World, geometry: &CollisionGeometry) -> Entity {
+        let (primitive, position) = geometry.primitive();
+
+        world
+            .create_entity()
+            .with_static_rigid_body(
+                CollisionShape2::<f32, BodyPose2<f32>, ()>::new_simple(
+                    CollisionStrategy::FullResolution,
+                    CollisionMode::Discrete,
+                    primitive,
+                ),
+                BodyPose2::new(position, Basis2::one()),
+                RigidBody::default(),
+                Mass2::new(1.0),
+            )
+            .build()
+    }
+
+    /// Adds collision to doors which have been shut and removes it from doors which have been
+    /// opened. Since the doors are then in the collision world, they also block the guards' sight.
+    fn sync_doors(&mut self) {
+        let changes: Vec<(usize, bool)> = self.world
+            .read_resource::<Doors>()
+            .0
+            .iter()
+            .enumerate()
+            .filter_map(|(index, door)| match (door.state != DoorState::Open, door.body) {
+                (true, None) => Some((index, true)),
+                (false, Some(_)) => Some((index, false)),
+                _ => None,
+            })
+            .collect();
+
+        for (index, shut) in changes {
+            if shut {
+                let bounds = self.world.read_resource::<Doors>().0[index].info.bounds;
+                let body =
+                    LevelState::create_static_body(&mut self.world, &CollisionGeometry::Rect(bounds));
+                self.world.write_resource::<Doors>().0[index].body = Some(body);
+            } else {
+                let body = self.world.write_resource::<Doors>().0[index].body.take();
+                if let Some(body) = body {
+                    self.world
+                        .entities()
+                        .delete(body)
+                        .expect("Failed to delete door body!");
+                }
+            }
+        }
+    }
+
+    /// Loads the level again from its map file, replacing the level geometry, the guard
+    /// navigation boxes, and the doors, keys and paintings. The player stays where they are unless
+    /// they would be stuck inside of the new geometry, in which case they're moved to the new
+    /// spawn.
+    fn reload(&mut self, ctx: &mut Context) {
+        let level = match Level::load(&self.level.file, ctx) {
+            Ok(level) => level,
+            Err(e) => {
+                eprintln!("Could not reload level: {}", e);
+                return;
+            }
+        };
+
+        {
+            let entities = self.world.entities();
+            for &body in &self.static_bodies {
+                entities.delete(body).expect("Failed to delete level body!");
+            }
+        }
+        self.static_bodies = LevelState::create_static_bodies(&mut self.world, &level);
+
+        self.world
+            .res
+            .add(GuardJumpBoxes(level.guard_jump_boxes.clone()));
+        self.world
+            .res
+            .add(GuardTurnAroundBoxes(level.guard_turn_around.clone()));
+        self.world
+            .res
+            .add(OneWayPlatforms(level.one_way_platforms.clone()));
+        self.world.res.add(Exits(level.exits.clone()));
+        self.world
+            .res
+            .add(HidingSpots(level.hiding_spots.clone()));
+        self.world.res.add(Shadows(level.shadows.clone()));
+        self.world.res.add(Ladders(level.ladders.clone()));
+        self.world.res.add(Tripwires(
+            level
+                .tripwires
+                .iter()
+                .map(|&bounds| Tripwire {
+                    bounds,
+                    tripped: false,
+                })
+                .collect(),
+        ));
+        self.world.res.add(level.nav.clone());
+        self.world.write_resource::<Camera>().map_size = level.size;
+
+        // What the guards remember about the level points into the old one, so they have to
+        // work it out again. The player comes out of hiding for the same reason.
+        for ai in (&mut self.world.write::<GuardAi>()).join() {
+            ai.path = None;
+            ai.path_target = None;
+            ai.repath_timer = 0.0;
+            ai.known_hiding_spot = None;
+            ai.searched_hiding_spots.clear();
+            ai.inspecting = None;
+            ai.inspected.clear();
+        }
+        self.world.write_resource::<GlobalPlayerState>().hiding = None;
+
+        // Doors, keys and paintings which are still in the same place keep whatever the player
+        // has done with them. The door bodies are made again once the doors have been rebuilt.
+        {
+            let entities = self.world.entities();
+            let mut doors = self.world.write_resource::<Doors>();
+            for door in doors.0.iter() {
+                if let Some(body) = door.body {
+                    entities.delete(body).expect("Failed to delete door body!");
+                }
+            }
+
+            let rebuilt = level
+                .doors
+                .iter()
+                .map(|info| {
+                    let old = doors.0.iter().find(|door| door.info.bounds == info.bounds);
+                    Door {
+                        info: info.clone(),
+                        state: old.map_or(info.state, |door| door.state),
+                        sealed: old.map_or(false, |door| door.sealed),
+                        body: None,
+                    }
+                })
+                .collect();
+            doors.0 = rebuilt;
+        }
+        {
+            let mut keys = self.world.write_resource::<Keys>();
+            let rebuilt = level
+                .keys
+                .iter()
+                .map(|info| KeyPickup {
+                    info: info.clone(),
+                    collected: keys.0.iter().any(|key| {
+                        key.collected && key.info.key == info.key && key.info.bounds == info.bounds
+                    }),
+                })
+                .collect();
+            keys.0 = rebuilt;
+        }
+        {
+            let mut paintings = self.world.write_resource::<Paintings>();
+            let rebuilt = level
+                .paintings
+                .iter()
+                .zip(level.painting_images.iter())
+                .map(|(info, image)| {
+                    let old = paintings
+                        .0
+                        .iter()
+                        .find(|painting| painting.info.painting == info.painting);
+                    PaintingState {
+                        info: info.clone(),
+                        original: image.clone(),
+                        replica: old.and_then(|painting| painting.replica.clone()),
+                        accuracy: old.and_then(|painting| painting.accuracy),
+                        status: old.map_or(PaintingStatus::Hanging, |painting| painting.status),
+                    }
+                })
+                .collect();
+            paintings.0 = rebuilt;
+        }
+
+        let stuck = {
+            let bounds = self.world.read_resource::<GlobalPlayerState>().bounds;
+            let inner = Aabb2::new(
+                Point2::new(
+                    bounds.min.x + RELOAD_OVERLAP_TOLERANCE,
+                    bounds.min.y + RELOAD_OVERLAP_TOLERANCE,
+                ),
+                Point2::new(
+                    bounds.max.x - RELOAD_OVERLAP_TOLERANCE,
+                    bounds.max.y - RELOAD_OVERLAP_TOLERANCE,
+                ),
+            );
+            level
+                .collision
+                .iter()
+                .any(|geometry| geometry.intersects(&inner))
+        };
+
+        if stuck {
+            let pose = BodyPose2::new(level.player_spawn, Basis2::one());
+            let velocity = Velocity2::new(Vector2::new(0.0, 0.0), 0.0);
+            self.world
+                .write::<BodyPose2<f32>>()
+                .insert(self.player, pose.clone());
+            self.world
+                .write::<NextFrame<BodyPose2<f32>>>()
+                .insert(self.player, NextFrame { value: pose });
+            self.world
+                .write::<Velocity2<f32>>()
+                .insert(self.player, velocity.clone());
+            self.world
+                .write::<NextFrame<Velocity2<f32>>>()
+                .insert(self.player, NextFrame { value: velocity });
+        }
+
+        self.watcher = LevelWatcher::new(&level);
+        self.level = level;
+        self.sync_doors();
     }
 
     fn update(&mut self, ctx: &mut Context, render_state: &mut RenderState) {
@@ -345,22 +791,50 @@ impl<'a> LevelState<'a> {
         let seconds =
             (dt.as_secs() as f32 + (dt.subsec_nanos() as f32 / 1_000_000_000.0)).min(1.0 / 20.0);
         self.world.write_resource::<DeltaTime<f32>>().delta_seconds = seconds;
-        if self.world.read_resource::<GlobalPlayerState>().captured {
-            render_state.gui = Some(Box::new(capture::CaptureGui));
-            self.locked = true;
+
+        // Rebuild the level whenever its map is saved, so that it can be edited while playing
+        if render_state.debug {
+            self.reload_timer -= seconds;
+            if self.reload_timer <= 0.0 {
+                self.reload_timer = RELOAD_POLL_INTERVAL;
+                if self.watcher.changed() {
+                    self.reload(ctx);
+                }
+            }
+        }
+
+        {
+            let progress = self.world.read_resource::<LevelProgress>();
+            if progress.complete {
+                if !self.locked {
+                    render_state.show_gui(Box::new(LevelCompleteGui::new(&progress, self.last_level)));
+                    self.locked = true;
+                }
+            } else if self.world.read_resource::<GlobalPlayerState>().captured {
+                render_state.show_gui(Box::new(capture::CaptureGui::new()));
+                self.locked = true;
+            }
         }
 
         self.update_dispatcher.dispatch(&self.world.res);
+        self.sync_doors();
         self.world.maintain();
     }
 
     fn render(&mut self, ctx: &mut Context, render_state: &mut RenderState) {
-        let resources = &mut self.world.res;
+        self.level.render(ctx, &self.world.read_resource::<Camera>());
 
-        self.level.render(ctx);
+        let resources = &mut self.world.res;
 
         // Run rendering systems
+        PaintingRenderSystem::new(ctx).run_now(resources);
+        DoorRenderSystem::new(ctx).run_now(resources);
         SpriteSystem::new(ctx).run_now(resources);
+        DistractionRenderSystem::new(ctx).run_now(resources);
+        ShadowRenderSystem::new(ctx).run_now(resources);
+        LightRenderSystem::new(ctx).run_now(resources);
+        SuspicionRenderSystem::new(ctx).run_now(resources);
+        AlarmRenderSystem::new(ctx).run_now(resources);
 
         if render_state.debug {
             DebugRenderSystem::new(ctx).run_now(resources);
@@ -372,44 +846,225 @@ impl<'a> LevelState<'a> {
             return;
         }
 
-        use rand::Rng;
-        use std::fs;
-
         let mut input = self.world.write_resource::<GameInput>();
 
         use Keycode::*;
         match keycode {
             Right => input.move_horizontal = 1.0,
             Left => input.move_horizontal = -1.0,
-            Up => input.jumping = true,
-            C => {
-                // check if player is in bounds of easel rect
-                let pos = self.world.read_resource::<GlobalPlayerState>().pos;
-                if self.level.easel_rect.contains(&pos) {
-                    render_state.gui = match render_state.gui {
-                        Some(_) => None,
-                        None => {
-                            let paintings = fs::read_dir("./resources/paintings").unwrap().map(|r| r.unwrap()).collect::<Vec<_>>();
-                            let chosen_file = rand::thread_rng().choose(paintings.as_ref());
-                            Some(Box::new(PaintingCanvas::from_path(
-                                ctx,
-                                chosen_file.unwrap().path(),
-                            )))
+            Up => {
+                input.jumping = true;
+                input.move_vertical = -1.0;
+            }
+            Down => {
+                input.dropping = true;
+                input.move_vertical = 1.0;
+            }
+            LShift => input.sneaking = true,
+            LCtrl => input.crouching = true,
+            T => input.aiming = true,
+            C => self.interact(ctx, render_state),
+            H => self.toggle_hiding(),
+            _ => (),
+        }
+    }
+
+    /// Throws a distraction along the player's aim, if they have any left and aren't hiding
+    fn throw_distraction(&mut self) {
+        let (origin, velocity) = {
+            let player_state = self.world.read_resource::<GlobalPlayerState>();
+            let mut inventory = self.world.write_resource::<Inventory>();
+            if inventory.distractions == 0 || player_state.hiding.is_some() {
+                return;
+            }
+            inventory.distractions -= 1;
+
+            let angle = self.world.read_resource::<Aim>().angle;
+            distraction::launch(&player_state, angle)
+        };
+
+        self.world
+            .create_entity()
+            .with(Distraction::new())
+            .with(CollisionState::new((DISTRACTION_SIZE, DISTRACTION_SIZE)))
+            .with_dynamic_rigid_body(
+                CollisionShape2::<f32, BodyPose2<f32>, ()>::new_simple(
+                    CollisionStrategy::FullResolution,
+                    CollisionMode::Discrete,
+                    Rectangle::new(DISTRACTION_SIZE, DISTRACTION_SIZE).into(),
+                ),
+                BodyPose2::new(origin, Basis2::one()),
+                Velocity2::new(velocity, 0.0),
+                RigidBody::default(),
+                Mass2::new(DISTRACTION_MASS),
+            )
+            .build();
+    }
+
+    /// Opens or shuts the door the player is next to, or otherwise takes down, paints or hangs up
+    /// whichever painting they're next to depending on what they're carrying
+    fn interact(&self, ctx: &mut Context, render_state: &mut RenderState) {
+        use rand::Rng;
+        use std::fs;
+
+        if render_state.gui.is_some() {
+            render_state.gui = None;
+            return;
+        }
+
+        let (pos, bounds) = {
+            let player_state = self.world.read_resource::<GlobalPlayerState>();
+            (player_state.pos, player_state.bounds)
+        };
+        // Doors take priority, since they're in the way
+        {
+            let mut doors = self.world.write_resource::<Doors>();
+            let inventory = self.world.read_resource::<Inventory>();
+            let reach = Aabb2::new(
+                Point2::new(bounds.min.x - PLAYER_DOOR_REACH, bounds.min.y),
+                Point2::new(bounds.max.x + PLAYER_DOOR_REACH, bounds.max.y),
+            );
+
+            if let Some(door) = doors.0.iter_mut().find(|door| door.info.bounds.intersects(&reach)) {
+                door.state = match door.state {
+                    // Don't shut the door on the player
+                    DoorState::Open if door.info.bounds.intersects(&bounds) => DoorState::Open,
+                    DoorState::Open => DoorState::Closed,
+                    DoorState::Closed => DoorState::Open,
+                    // Keys don't open doors sealed by a lockdown
+                    DoorState::Locked if door.sealed => DoorState::Locked,
+                    DoorState::Locked => {
+                        let has_key = match door.info.key {
+                            Some(ref key) => inventory.keys.contains(key),
+                            None => false,
+                        };
+                        if has_key {
+                            DoorState::Open
+                        } else {
+                            DoorState::Locked
                         }
                     }
-                }
+                };
+                return;
             }
-            _ => (),
         }
+
+        let at_easel = self.level.easel_rect.contains(&pos);
+        let mut paintings = self.world.write_resource::<Paintings>();
+
+        if paintings.0.is_empty() {
+            // Without any paintings to steal, paint a random one at the easel
+            if at_easel {
+                let paintings = fs::read_dir("./resources/paintings").unwrap().map(|r| r.unwrap()).collect::<Vec<_>>();
+                let chosen_file = rand::thread_rng().choose(paintings.as_ref());
+                render_state.show_gui(Box::new(PaintingCanvas::from_path(
+                    ctx,
+                    chosen_file.unwrap().path(),
+                )));
+            }
+            return;
+        }
+
+        if let Some(painting) = paintings.carried() {
+            if painting.status == PaintingStatus::Carried && at_easel {
+                render_state.show_gui(Box::new(PaintingCanvas::from_path(
+                    ctx,
+                    painting.info.path(),
+                )));
+            } else if painting.status == PaintingStatus::Painted
+                && painting.info.bounds.intersects(&bounds)
+            {
+                painting.status = PaintingStatus::Swapped;
+            }
+            return;
+        }
+
+        let hanging = paintings.0.iter_mut().find(|painting| {
+            painting.status == PaintingStatus::Hanging && painting.info.bounds.intersects(&bounds)
+        });
+        if let Some(painting) = hanging {
+            painting.status = PaintingStatus::Carried;
+        }
+    }
+
+    /// Hides the player in the hiding spot they're standing in, or brings them back out of hiding
+    fn toggle_hiding(&self) {
+        let hiding_spots = self.world.read_resource::<HidingSpots>();
+        let mut player_state = self.world.write_resource::<GlobalPlayerState>();
+
+        player_state.hiding = match player_state.hiding {
+            Some(_) => None,
+            None => {
+                let pos = player_state.pos;
+                hiding_spots.0.iter().position(|spot| spot.contains(&pos))
+            }
+        };
+    }
+
+    /// Called when a replica has been painted at the easel
+    fn painting_finished(
+        &mut self,
+        ctx: &mut Context,
+        accuracy: f32,
+        replica: RgbaImage,
+    ) -> GameResult<()> {
+        let mut paintings = self.world.write_resource::<Paintings>();
+
+        match paintings.carried() {
+            Some(painting) => {
+                painting.replica = Some(graphics::Image::from_rgba8(
+                    ctx,
+                    replica.width() as u16,
+                    replica.height() as u16,
+                    &replica,
+                )?);
+                painting.accuracy = Some(accuracy);
+                painting.status = PaintingStatus::Painted;
+            }
+            None => {
+                // Levels without paintings are finished as soon as the replica is painted
+                let mut progress = self.world.write_resource::<LevelProgress>();
+                progress.accuracy = Some(accuracy);
+                progress.painting_swapped = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resize(&mut self, logical_size: (f32, f32)) {
+        self.world.write_resource::<Camera>().resize(logical_size);
     }
 
     fn key_released(&mut self, keycode: Keycode) {
+        // Letting go of the throw key makes the throw
+        if keycode == Keycode::T {
+            if self.world.read_resource::<GameInput>().aiming {
+                self.world.write_resource::<GameInput>().aiming = false;
+                self.throw_distraction();
+            }
+            return;
+        }
+
         let mut input = self.world.write_resource::<GameInput>();
 
         use Keycode::*;
         match keycode {
             Right | Left => input.move_horizontal = 0.0,
-            Up => input.jumping = false,
+            Up => {
+                input.jumping = false;
+                if input.move_vertical < 0.0 {
+                    input.move_vertical = 0.0;
+                }
+            }
+            Down => {
+                input.dropping = false;
+                if input.move_vertical > 0.0 {
+                    input.move_vertical = 0.0;
+                }
+            }
+            LShift => input.sneaking = false,
+            LCtrl => input.crouching = false,
             _ => (),
         }
     }
@@ -420,9 +1075,10 @@ fn main() {
         .window_setup(
             WindowSetup::default()
                 .title("Tale of an Arts Major")
-                .icon("/icon.png"),
+                .icon("/icon.png")
+                .resizable(true),
         )
-        .window_mode(WindowMode::default().dimensions(SCREEN_SIZE.0, SCREEN_SIZE.1))
+        .window_mode(WindowMode::default().dimensions(WINDOW_SIZE.0, WINDOW_SIZE.1))
         .build()
         .expect("Failed to build ggez context");
 
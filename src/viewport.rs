@@ -0,0 +1,85 @@
+use ggez::{self, GameResult, graphics};
+
+/// How the logical pixels the game is drawn in map onto the window.
+///
+/// Logical pixels are scaled up by a whole number so that the pixel art stays sharp, and whatever
+/// is left over around the edges of the window is covered with black bars.
+#[derive(Debug, Copy, Clone)]
+pub struct Viewport {
+    /// How many window pixels wide each logical pixel is
+    pub scale: u32,
+    /// The size of the visible area, in logical pixels
+    pub logical_size: (f32, f32),
+    /// The size of the bars on the left and top of the window, in window pixels
+    offset: (u32, u32),
+    window_size: (u32, u32),
+}
+
+impl Viewport {
+    pub fn new(width: u32, height: u32) -> Self {
+        let scale = (width / ::MIN_LOGICAL_SIZE.0)
+            .min(height / ::MIN_LOGICAL_SIZE.1)
+            .max(1);
+        let logical_width = width / scale;
+        let logical_height = height / scale;
+
+        Viewport {
+            scale,
+            logical_size: (logical_width as f32, logical_height as f32),
+            offset: (
+                (width - logical_width * scale) / 2,
+                (height - logical_height * scale) / 2,
+            ),
+            window_size: (width, height),
+        }
+    }
+
+    /// Sets up the projection so that everything after this is drawn in logical pixels, with the
+    /// origin at the top left of the visible area
+    pub fn apply(&self, ctx: &mut ggez::Context) -> GameResult<()> {
+        let scale = self.scale as f32;
+        graphics::set_screen_coordinates(
+            ctx,
+            graphics::Rect::new(
+                -(self.offset.0 as f32) / scale,
+                -(self.offset.1 as f32) / scale,
+                self.window_size.0 as f32 / scale,
+                self.window_size.1 as f32 / scale,
+            ),
+        )
+    }
+
+    /// Converts a position in the window, such as the mouse position, to logical pixels
+    pub fn to_logical(&self, x: f32, y: f32) -> (f32, f32) {
+        let scale = self.scale as f32;
+        (
+            (x - self.offset.0 as f32) / scale,
+            (y - self.offset.1 as f32) / scale,
+        )
+    }
+
+    /// Covers up anything which was drawn outside of the visible area
+    pub fn draw_letterbox(&self, ctx: &mut ggez::Context) -> GameResult<()> {
+        let scale = self.scale as f32;
+        let left = self.offset.0 as f32 / scale;
+        let top = self.offset.1 as f32 / scale;
+        let width = self.window_size.0 as f32 / scale;
+        let height = self.window_size.1 as f32 / scale;
+        let (logical_width, logical_height) = self.logical_size;
+
+        let bars = [
+            graphics::Rect::new(-left, -top, left, height),
+            graphics::Rect::new(logical_width, -top, width - left - logical_width, height),
+            graphics::Rect::new(-left, -top, width, top),
+            graphics::Rect::new(-left, logical_height, width, height - top - logical_height),
+        ];
+
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 1.0))?;
+        for bar in bars.iter().filter(|bar| bar.w > 0.0 && bar.h > 0.0) {
+            graphics::rectangle(ctx, graphics::DrawMode::Fill, *bar)?;
+        }
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+
+        Ok(())
+    }
+}